@@ -0,0 +1,35 @@
+use {ansion::prelude::*, failure::Error};
+
+fn main() -> Result<(), Error> {
+    let mut t = ansi_terminal()?;
+    t.set_mode(TerminalModeOptions::raw())?;
+    out!(
+        t,
+        EnableMouseTracking(MouseTrackingMode::ButtonEvent),
+        EnableMouseEncoding(MouseEncoding::Sgr),
+        EnableBracketedPaste,
+        EnableFocusReporting,
+        ("Press keys, paste or use the mouse to see their events, or 'q' to quit.\r\n"),
+    );
+    t.flush()?;
+    loop {
+        let event = t.read_event()?;
+        out!(t, ("{:?}\r\n", event));
+        t.flush()?;
+        if let Event::Key(KeyEvent {
+            code: KeyCode::Char('q'),
+            ..
+        }) = event
+        {
+            break;
+        }
+    }
+    out!(
+        t,
+        DisableFocusReporting,
+        DisableBracketedPaste,
+        DisableMouseEncoding(MouseEncoding::Sgr),
+        DisableMouseTracking(MouseTrackingMode::ButtonEvent),
+    );
+    Ok(())
+}
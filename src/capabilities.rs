@@ -0,0 +1,292 @@
+//! Guesses what the terminal supports from the environment it set up for the process. This can't
+//! be perfect, since environment variables aren't always forwarded, like over SSH.
+
+use std::env;
+
+/// Represents how many colors a terminal can show, from least to most.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ColorDepth {
+    /// No colors at all, like when output doesn't go to a terminal or the user asked for none.
+    Monochrome,
+    /// The 8 `PresetColor`s, along with their bright versions.
+    Ansi16,
+    /// The 256-color table that `ColorTableValue` indexes.
+    Ansi256,
+    /// Any `Rgb` color.
+    TrueColor,
+}
+
+/// Represents what a terminal supports beyond the basics.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Capabilities {
+    pub color_depth: ColorDepth,
+    /// Whether `OscEscape::OpenHyperlink` makes links.
+    pub hyperlinks: bool,
+    /// Whether `SetGraphicsRenditionEscape::StyledUnderline` and `UnderlineColor` work.
+    pub styled_underlines: bool,
+    /// Whether `AnsiEscape::BeginSynchronizedUpdate` does anything.
+    pub synchronized_output: bool,
+}
+
+impl Capabilities {
+    /// Detects capabilities from the process's environment. `is_tty` is whether output goes to a
+    /// terminal; `AnsiTerminal::capabilities` fills it in.
+    pub fn detect(is_tty: bool) -> Self {
+        Capabilities::from_env(is_tty, |name| env::var(name).ok())
+    }
+
+    /// Like `detect`, but looks up environment variables with `var` instead. These are consulted:
+    ///
+    /// * `NO_COLOR`, which turns off color when set to anything
+    /// * `CLICOLOR=0`, which turns off color, and `CLICOLOR_FORCE`, which turns color on even
+    ///   when output doesn't go to a terminal and takes precedence over `NO_COLOR`
+    /// * `COLORTERM`, `TERM` and `TERM_PROGRAM`, along with variables that specific terminals set,
+    ///   to identify the terminal and how many colors it supports
+    /// * `TMUX`, since multiplexers don't pass on most newer escapes
+    pub fn from_env<F>(is_tty: bool, var: F) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let set = |name| var(name).is_some_and(|v| !v.is_empty());
+        let term = var("TERM").unwrap_or_default().to_ascii_lowercase();
+        let term_program = var("TERM_PROGRAM").unwrap_or_default().to_ascii_lowercase();
+        let vte_version = var("VTE_VERSION")
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(0);
+
+        let is = |name: &str| term.contains(name) || term_program.contains(name);
+        let kitty = is("kitty") || set("KITTY_WINDOW_ID");
+        let wezterm = is("wezterm") || set("WEZTERM_EXECUTABLE");
+        let foot = term.starts_with("foot");
+        let ghostty = is("ghostty");
+
+        let forced = var("CLICOLOR_FORCE").is_some_and(|v| !v.is_empty() && v != "0");
+        let disabled = set("NO_COLOR") || var("CLICOLOR").as_deref() == Some("0");
+        let color_depth = if !forced && (disabled || !is_tty || term == "dumb") {
+            ColorDepth::Monochrome
+        } else if ["truecolor", "24bit"].contains(&var("COLORTERM").unwrap_or_default().as_str())
+            || term.ends_with("-direct")
+            || kitty
+            || wezterm
+            || foot
+            || ghostty
+            || ["iterm.app", "vscode"].contains(&term_program.as_str())
+            || set("WT_SESSION")
+        {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") || term_program == "apple_terminal" {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        };
+
+        // Multiplexers sit between the application and the terminal, and swallow what they don't
+        // understand themselves.
+        let multiplexed = set("TMUX") || term.starts_with("screen") || term.starts_with("tmux");
+        let usable = is_tty && !multiplexed;
+
+        Capabilities {
+            color_depth,
+            hyperlinks: usable
+                && (kitty
+                    || wezterm
+                    || foot
+                    || ghostty
+                    || ["iterm.app", "vscode"].contains(&term_program.as_str())
+                    || set("WT_SESSION")
+                    || vte_version >= 5000),
+            styled_underlines: usable
+                && (kitty || wezterm || foot || ghostty || vte_version >= 5102),
+            synchronized_output: usable
+                && (kitty || wezterm || foot || ghostty || term_program == "iterm.app"),
+        }
+    }
+}
+
+/// The terminal's answer to `AnsiEscape::QueryPrimaryDeviceAttributes`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PrimaryDeviceAttributes {
+    /// The level of the VT series that the terminal claims to conform to, like 62 for a VT220 or
+    /// 65 for a VT525. Terminals that only emulate a VT100 report 1.
+    pub conformance_level: u16,
+    /// The features the terminal claims to support, like 4 for sixel graphics or 22 for ANSI
+    /// color.
+    pub attributes: Vec<u16>,
+}
+
+/// The terminal's answer to `AnsiEscape::QuerySecondaryDeviceAttributes`. What the numbers mean
+/// varies between terminals; for instance, xterm reports 41 as its type and its patch number as
+/// its version, and VTE reports 65 and its version number.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SecondaryDeviceAttributes {
+    pub terminal_type: u16,
+    pub version: u16,
+}
+
+/// The terminal's answer to `AnsiEscape::QueryPrivateMode`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ModeState {
+    /// The terminal doesn't know the mode, or didn't answer at all.
+    NotRecognized,
+    Set,
+    Reset,
+    /// The mode is always set and can't be changed.
+    PermanentlySet,
+    /// The mode is always reset and can't be changed.
+    PermanentlyReset,
+}
+
+impl ModeState {
+    /// Whether the terminal knows the mode, which is what matters for features like
+    /// `AnsiEscape::BeginSynchronizedUpdate` that are turned on as they're used.
+    pub fn is_recognized(self) -> bool {
+        self != ModeState::NotRecognized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorDepth::*, *};
+
+    /// Environment variables, as names and values
+    type Vars = &'static [(&'static str, &'static str)];
+
+    fn detect(is_tty: bool, vars: Vars) -> Capabilities {
+        Capabilities::from_env(is_tty, |name| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn color_depth() {
+        let cases: &[(bool, Vars, ColorDepth)] = &[
+            (true, &[("TERM", "xterm")], Ansi16),
+            (true, &[("TERM", "xterm-256color")], Ansi256),
+            (true, &[("TERM", "xterm-direct")], TrueColor),
+            (
+                true,
+                &[("TERM", "xterm"), ("COLORTERM", "truecolor")],
+                TrueColor,
+            ),
+            (
+                true,
+                &[("TERM", "xterm"), ("COLORTERM", "24bit")],
+                TrueColor,
+            ),
+            (
+                true,
+                &[("TERM", "dumb"), ("COLORTERM", "truecolor")],
+                Monochrome,
+            ),
+            (false, &[("TERM", "xterm-256color")], Monochrome),
+            (
+                true,
+                &[("TERM", "xterm-256color"), ("NO_COLOR", "1")],
+                Monochrome,
+            ),
+            (
+                true,
+                &[("TERM", "xterm-256color"), ("NO_COLOR", "")],
+                Ansi256,
+            ),
+            (
+                true,
+                &[("TERM", "xterm-256color"), ("CLICOLOR", "0")],
+                Monochrome,
+            ),
+            (
+                true,
+                &[("TERM", "xterm-256color"), ("CLICOLOR", "1")],
+                Ansi256,
+            ),
+            (
+                false,
+                &[("TERM", "xterm-256color"), ("CLICOLOR_FORCE", "1")],
+                Ansi256,
+            ),
+            (
+                false,
+                &[("TERM", "xterm-256color"), ("CLICOLOR_FORCE", "0")],
+                Monochrome,
+            ),
+            (
+                true,
+                &[
+                    ("TERM", "xterm"),
+                    ("NO_COLOR", "1"),
+                    ("CLICOLOR_FORCE", "1"),
+                ],
+                Ansi16,
+            ),
+            (true, &[("TERM", "xterm-kitty")], TrueColor),
+            (
+                true,
+                &[("TERM", "xterm-256color"), ("TERM_PROGRAM", "iTerm.app")],
+                TrueColor,
+            ),
+            // Multiplexers pass colors on.
+            (
+                true,
+                &[("TERM", "screen-256color"), ("COLORTERM", "truecolor")],
+                TrueColor,
+            ),
+            (true, &[("TERM", "tmux-256color")], Ansi256),
+        ];
+        for &(is_tty, vars, color_depth) in cases {
+            let detected = detect(is_tty, vars).color_depth;
+            assert_eq!(detected, color_depth, "is_tty: {}, {:?}", is_tty, vars);
+        }
+    }
+
+    #[test]
+    fn features() {
+        // Whether hyperlinks, styled underlines and synchronized output are supported
+        let cases: &[(bool, Vars, (bool, bool, bool))] = &[
+            (true, &[("TERM", "xterm-256color")], (false, false, false)),
+            (true, &[("TERM", "xterm-kitty")], (true, true, true)),
+            (
+                true,
+                &[("TERM", "xterm"), ("KITTY_WINDOW_ID", "1")],
+                (true, true, true),
+            ),
+            (false, &[("TERM", "xterm-kitty")], (false, false, false)),
+            (true, &[("TERM_PROGRAM", "iTerm.app")], (true, false, true)),
+            (true, &[("VTE_VERSION", "4999")], (false, false, false)),
+            (true, &[("VTE_VERSION", "5000")], (true, false, false)),
+            (true, &[("VTE_VERSION", "5101")], (true, false, false)),
+            (true, &[("VTE_VERSION", "5102")], (true, true, false)),
+            // Multiplexers swallow escapes that the terminal outside would understand.
+            (
+                true,
+                &[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux-0/default")],
+                (false, false, false),
+            ),
+            (
+                true,
+                &[("TERM", "screen-256color"), ("VTE_VERSION", "7000")],
+                (false, false, false),
+            ),
+            (
+                true,
+                &[("TERM", "screen"), ("KITTY_WINDOW_ID", "1")],
+                (false, false, false),
+            ),
+        ];
+        for &(is_tty, vars, features) in cases {
+            let detected = detect(is_tty, vars);
+            assert_eq!(
+                (
+                    detected.hyperlinks,
+                    detected.styled_underlines,
+                    detected.synchronized_output,
+                ),
+                features,
+                "is_tty: {}, {:?}",
+                is_tty,
+                vars
+            );
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use {crate::TerminalOutput, std::io};
+use {
+    crate::{escapes::Observe, TerminalOutput},
+    std::io,
+};
 
 #[derive(Clone, Debug)]
 pub enum CursorEscape {
@@ -16,6 +19,23 @@ pub enum CursorEscape {
     DisableBlinking,
     Show,
     Hide,
+    /// Asks the terminal to report the cursor position as input. See
+    /// `AnsiTerminal::query_cursor_position`.
+    QueryPosition,
+    SetCursorStyle {
+        shape: CursorShape,
+        blinking: bool,
+    },
+    /// Goes back to the cursor style that the user configured for the terminal. `AnsiTerminal`s
+    /// do this when they're dropped, if they changed the style.
+    DefaultCursorStyle,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CursorShape {
+    Block,
+    Underline,
+    Bar,
 }
 
 impl TerminalOutput for CursorEscape {
@@ -41,6 +61,75 @@ impl TerminalOutput for CursorEscape {
             DisableBlinking => write_csi!("?12l";),
             Show => write_csi!("?25h";),
             Hide => write_csi!("?25l";),
+            QueryPosition => write_csi!("6n";),
+            SetCursorStyle { shape, blinking } => {
+                let steady = match shape {
+                    CursorShape::Block => 2,
+                    CursorShape::Underline => 4,
+                    CursorShape::Bar => 6,
+                };
+                write_csi!("{} q"; steady - *blinking as u8)
+            }
+            DefaultCursorStyle => write_csi!("0 q";),
+        }
+    }
+}
+
+/// Watches the bytes written to a terminal for `CursorEscape::SetCursorStyle`, so that the style
+/// only gets reset if something changed it.
+#[derive(Debug, Default)]
+pub(crate) struct CursorStyleTracker {
+    changed: bool,
+    /// How far into `ESC[n q` the last bytes written got
+    matched: u8,
+}
+
+impl Observe for CursorStyleTracker {
+    fn observe(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.matched = match (self.matched, b) {
+                (_, b'\x1B') => 1,
+                (1, b'[') => 2,
+                (2, b'0'..=b'9') => 2,
+                (2, b' ') => 3,
+                (3, b'q') => {
+                    self.changed = true;
+                    0
+                }
+                _ => 0,
+            };
+        }
+    }
+}
+
+impl CursorStyleTracker {
+    /// Whether a cursor style has been written
+    pub(crate) fn changed(&self) -> bool {
+        self.changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Write};
+
+    #[test]
+    fn tracks_cursor_style_changes() {
+        let mut out = Vec::new();
+        let mut tracker = CursorStyleTracker::default();
+        write!(tracker.track(&mut out), "\x1B[2J\x1B[6qtext q").unwrap();
+        assert!(!tracker.changed());
+
+        let style = CursorEscape::SetCursorStyle {
+            shape: CursorShape::Bar,
+            blinking: true,
+        };
+        let mut formatted = Vec::new();
+        style.fmt(&mut formatted).unwrap();
+        // Split across writes, like a buffered writer might do.
+        for byte in formatted.chunks(1) {
+            tracker.track(&mut out).write_all(byte).unwrap();
         }
+        assert!(tracker.changed());
     }
 }
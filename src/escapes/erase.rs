@@ -0,0 +1,44 @@
+/// Represents which part of the display `AnsiEscape::EraseInDisplay` erases.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DisplayErasure {
+    /// From the cursor to the end of the display.
+    ToEnd,
+    /// From the start of the display to the cursor.
+    ToStart,
+    All,
+    /// The lines that have scrolled off the top of the display, which not every terminal keeps.
+    Scrollback,
+}
+
+impl DisplayErasure {
+    pub(crate) fn parameter(self) -> u16 {
+        use self::DisplayErasure::*;
+        match self {
+            ToEnd => 0,
+            ToStart => 1,
+            All => 2,
+            Scrollback => 3,
+        }
+    }
+}
+
+/// Represents which part of the cursor's line `AnsiEscape::EraseInLine` erases.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineErasure {
+    /// From the cursor to the end of the line.
+    ToEnd,
+    /// From the start of the line to the cursor.
+    ToStart,
+    All,
+}
+
+impl LineErasure {
+    pub(crate) fn parameter(self) -> u16 {
+        use self::LineErasure::*;
+        match self {
+            ToEnd => 0,
+            ToStart => 1,
+            All => 2,
+        }
+    }
+}
@@ -1,13 +1,35 @@
-use {crate::TerminalOutput, std::io};
+use {
+    crate::{capabilities::ColorDepth, TerminalOutput},
+    std::io,
+};
 
 #[derive(Clone, Debug)]
 pub enum SetGraphicsRenditionEscape {
     Reset,
     Bright,
+    Faint,
+    /// Turns off both `Bright` and `Faint`.
+    NormalIntensity,
+    Italic,
+    NoItalic,
     Underline,
+    /// Not supported by every terminal; those that don't tend to fall back to `Underline`.
+    StyledUnderline(UnderlineStyle),
     NoUnderline,
+    /// Not supported by every terminal.
+    UnderlineColor(ExtendedColor),
+    UnderlineColorDefault,
+    SlowBlink,
+    RapidBlink,
+    NoBlink,
     Negative,
     Positive,
+    Conceal,
+    Reveal,
+    CrossedOut,
+    NoCrossedOut,
+    Overline,
+    NoOverline,
     ForegroundBlack,
     ForegroundRed,
     ForegroundGreen,
@@ -57,10 +79,30 @@ impl TerminalOutput for SetGraphicsRenditionEscape {
         match self {
             Reset => w!(0),
             Bright => w!(1),
+            Faint => w!(2),
+            NormalIntensity => w!(22),
+            Italic => w!(3),
+            NoItalic => w!(23),
             Underline => w!(4),
+            StyledUnderline(s) => write!(f, csi!("4:{}m"), s.code()),
             NoUnderline => w!(24),
+            UnderlineColor(e) => {
+                write!(f, csi!("58;"))?;
+                e.write_color_code(f)?;
+                write!(f, "m")
+            }
+            UnderlineColorDefault => w!(59),
+            SlowBlink => w!(5),
+            RapidBlink => w!(6),
+            NoBlink => w!(25),
             Negative => w!(7),
             Positive => w!(27),
+            Conceal => w!(8),
+            Reveal => w!(28),
+            CrossedOut => w!(9),
+            NoCrossedOut => w!(29),
+            Overline => w!(53),
+            NoOverline => w!(55),
             ForegroundBlack => w!(30),
             ForegroundRed => w!(31),
             ForegroundGreen => w!(32),
@@ -109,6 +151,52 @@ impl TerminalOutput for SetGraphicsRenditionEscape {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnderlineStyle {
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+impl UnderlineStyle {
+    fn code(self) -> u8 {
+        use self::UnderlineStyle::*;
+        match self {
+            Single => 1,
+            Double => 2,
+            Curly => 3,
+            Dotted => 4,
+            Dashed => 5,
+        }
+    }
+}
+
+/// The colors xterm uses for the preset colors by default, in the order of their SGR codes and
+/// the color table.
+const PRESET_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The intensities that each channel of the color table's 6x6x6 cube steps through.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
 #[derive(Clone, Debug)]
 pub struct ColorTableValue(pub u8);
 
@@ -119,6 +207,44 @@ impl ColorTableValue {
     }
 }
 
+impl ColorTableValue {
+    /// Gets the color this shows as in xterm's default color table. Most terminals let users
+    /// change the first 16 entries, so those are only a guess.
+    pub fn to_rgb(&self) -> Rgb {
+        let v = self.0;
+        match v {
+            0..=15 => {
+                let (r, g, b) = PRESET_RGB[usize::from(v)];
+                Rgb(r, g, b)
+            }
+            16..=231 => {
+                let i = usize::from(v - 16);
+                Rgb(
+                    CUBE_LEVELS[i / 36],
+                    CUBE_LEVELS[i / 6 % 6],
+                    CUBE_LEVELS[i % 6],
+                )
+            }
+            _ => {
+                let grey = 8 + 10 * (v - 232);
+                Rgb(grey, grey, grey)
+            }
+        }
+    }
+
+    /// Finds the preset color closest to this one.
+    pub fn to_preset_color_spec(&self) -> PresetColorSpec {
+        PresetColorSpec::from_index(self.preset_index())
+    }
+
+    fn preset_index(&self) -> u8 {
+        match self.0 {
+            v @ 0..=15 => v,
+            _ => self.to_rgb().preset_index(),
+        }
+    }
+}
+
 impl TerminalOutput for ColorTableValue {
     fn fmt(&self, f: &mut io::Write) -> io::Result<()> {
         TerminalOutput::fmt(&ExtendedColor::ColorTable(self.clone()), f)
@@ -135,6 +261,51 @@ impl Rgb {
     }
 }
 
+impl Rgb {
+    /// Finds the color closest to this one in the 6x6x6 cube and grey ramp of the color table.
+    /// The first 16 entries are left out, since users can change them.
+    pub fn to_color_table_value(&self) -> ColorTableValue {
+        let &Rgb(r, g, b) = self;
+        let level = |v: u8| match v {
+            0..=47 => 0,
+            48..=114 => 1,
+            _ => (v - 35) / 40,
+        };
+        let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+        let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+        let grey = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+        let [cube, grey] = [cube, grey].map(ColorTableValue);
+        match self.distance(&cube.to_rgb()) <= self.distance(&grey.to_rgb()) {
+            true => cube,
+            false => grey,
+        }
+    }
+
+    /// Finds the preset color closest to this one, going by xterm's default colors for them.
+    pub fn to_preset_color_spec(&self) -> PresetColorSpec {
+        PresetColorSpec::from_index(self.preset_index())
+    }
+
+    fn preset_index(&self) -> u8 {
+        (0..16)
+            .min_by_key(|&i| {
+                let (r, g, b) = PRESET_RGB[usize::from(i)];
+                self.distance(&Rgb(r, g, b))
+            })
+            .unwrap()
+    }
+
+    fn distance(&self, other: &Rgb) -> u32 {
+        let &Rgb(r1, g1, b1) = self;
+        let &Rgb(r2, g2, b2) = other;
+        [(r1, r2), (g1, g2), (b1, b2)]
+            .iter()
+            .map(|&(a, b)| u32::from(a.abs_diff(b)).pow(2))
+            .sum()
+    }
+}
+
 impl TerminalOutput for Rgb {
     fn fmt(&self, f: &mut io::Write) -> io::Result<()> {
         TerminalOutput::fmt(&ExtendedColor::Rgb(self.clone()), f)
@@ -201,8 +372,27 @@ impl TerminalOutput for PresetColor {
 
 #[derive(Clone, Debug)]
 pub struct PresetColorSpec {
-    color: PresetColor,
-    bright: bool,
+    pub color: PresetColor,
+    pub bright: bool,
+}
+
+impl PresetColorSpec {
+    fn from_index(i: u8) -> Self {
+        use self::PresetColor::*;
+        PresetColorSpec {
+            color: match i % 8 {
+                0 => Black,
+                1 => Red,
+                2 => Green,
+                3 => Yellow,
+                4 => Blue,
+                5 => Magenta,
+                6 => Cyan,
+                _ => White,
+            },
+            bright: i >= 8,
+        }
+    }
 }
 
 impl TerminalOutput for PresetColorSpec {
@@ -260,23 +450,82 @@ impl TerminalOutput for ColorSpec {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlinkSpeed {
+    Slow,
+    Rapid,
+}
+
+/// Every attribute that text can have besides its colors. It's written as escapes that set or
+/// clear each one, so that nothing carries over from whatever was written before. The default
+/// has all of them off; set the ones that are wanted with struct update syntax, like
+/// `Style { italic: true, ..Style::default() }`.
+#[derive(Clone, Debug, Default)]
 pub struct Style {
-    underline: bool,
-    negative: bool,
+    pub bright: bool,
+    pub faint: bool,
+    pub italic: bool,
+    pub underline: Option<UnderlineStyle>,
+    /// The color of the underline, instead of the text's color. Not supported by every terminal.
+    pub underline_color: Option<ExtendedColor>,
+    pub blink: Option<BlinkSpeed>,
+    pub negative: bool,
+    pub conceal: bool,
+    pub crossed_out: bool,
+    pub overline: bool,
 }
 
 impl TerminalOutput for Style {
     fn fmt(&self, f: &mut io::Write) -> io::Result<()> {
         use self::SetGraphicsRenditionEscape::*;
         let Style {
+            bright,
+            faint,
+            italic,
             underline,
+            underline_color,
+            blink,
             negative,
+            conceal,
+            crossed_out,
+            overline,
         } = self;
+        // Bright and faint are turned off together, so both have to be set again afterwards.
+        TerminalOutput::fmt(&NormalIntensity, f)?;
+        if *bright {
+            TerminalOutput::fmt(&Bright, f)?;
+        }
+        if *faint {
+            TerminalOutput::fmt(&Faint, f)?;
+        }
+        TerminalOutput::fmt(
+            &match italic {
+                true => Italic,
+                false => NoItalic,
+            },
+            f,
+        )?;
         TerminalOutput::fmt(
             &match underline {
-                true => Underline,
-                false => NoUnderline,
+                // Plain underlines work everywhere, so don't risk using the styled escape for it.
+                Some(UnderlineStyle::Single) => Underline,
+                Some(s) => StyledUnderline(*s),
+                None => NoUnderline,
+            },
+            f,
+        )?;
+        TerminalOutput::fmt(
+            &match underline_color {
+                Some(c) => UnderlineColor(c.clone()),
+                None => UnderlineColorDefault,
+            },
+            f,
+        )?;
+        TerminalOutput::fmt(
+            &match blink {
+                Some(BlinkSpeed::Slow) => SlowBlink,
+                Some(BlinkSpeed::Rapid) => RapidBlink,
+                None => NoBlink,
             },
             f,
         )?;
@@ -287,15 +536,37 @@ impl TerminalOutput for Style {
             },
             f,
         )?;
+        TerminalOutput::fmt(
+            &match conceal {
+                true => Conceal,
+                false => Reveal,
+            },
+            f,
+        )?;
+        TerminalOutput::fmt(
+            &match crossed_out {
+                true => CrossedOut,
+                false => NoCrossedOut,
+            },
+            f,
+        )?;
+        TerminalOutput::fmt(
+            &match overline {
+                true => Overline,
+                false => NoOverline,
+            },
+            f,
+        )?;
         Ok(())
     }
 }
 
+/// A `Style` along with the colors to write text in.
 #[derive(Clone, Debug)]
 pub struct FontSpec {
-    style: Style,
-    foreground_color: ColorSpec,
-    background_color: ColorSpec,
+    pub style: Style,
+    pub foreground_color: ColorSpec,
+    pub background_color: ColorSpec,
 }
 
 impl TerminalOutput for FontSpec {
@@ -350,3 +621,382 @@ impl TerminalOutput for FontSpec {
         Ok(())
     }
 }
+
+/// Formats `t`, rewriting the colors in the SGR escapes it writes to ones that a terminal with
+/// `depth` colors can show.
+pub(crate) fn fmt_downsampled<T: TerminalOutput>(
+    t: &T,
+    depth: ColorDepth,
+    f: &mut io::Write,
+) -> io::Result<()> {
+    if depth == ColorDepth::TrueColor {
+        return t.fmt(f);
+    }
+    let mut formatted = Vec::new();
+    t.fmt(&mut formatted)?;
+
+    let mut downsampled = Vec::with_capacity(formatted.len());
+    let mut rest = &formatted[..];
+    while let Some(start) = rest.windows(2).position(|w| w == b"\x1B[") {
+        downsampled.extend_from_slice(&rest[..start]);
+        let escape = &rest[start + 2..];
+        let parameters = escape
+            .iter()
+            .take_while(|b| (0x30..=0x3F).contains(*b))
+            .count();
+        match escape.get(parameters) {
+            Some(b'm') => {
+                // Parameter bytes are all ASCII.
+                let parameters = std::str::from_utf8(&escape[..parameters]).unwrap();
+                downsample_sgr(parameters, depth, &mut downsampled);
+                rest = &escape[parameters.len() + 1..];
+            }
+            _ => {
+                downsampled.extend_from_slice(b"\x1B[");
+                rest = escape;
+            }
+        }
+    }
+    downsampled.extend_from_slice(rest);
+    f.write_all(&downsampled)
+}
+
+fn downsample_sgr(parameters: &str, depth: ColorDepth, out: &mut Vec<u8>) {
+    // An empty SGR is a reset, and private ones, like xterm's `CSI > 4 ; 2 m`, aren't SGRs at
+    // all.
+    if parameters.is_empty() || parameters.starts_with(['<', '=', '>', '?']) {
+        out.extend_from_slice(format!(csi!("{}m"), parameters).as_bytes());
+        return;
+    }
+
+    let mut kept = Vec::new();
+    let mut parameters = parameters.split(';');
+    while let Some(parameter) = parameters.next() {
+        let mut subparameters = parameter.split(':');
+        match subparameters.next().unwrap().parse::<u8>() {
+            Ok(30..=37) | Ok(39) | Ok(40..=47) | Ok(49) | Ok(90..=97) | Ok(100..=107) => {
+                if depth != ColorDepth::Monochrome {
+                    kept.push(parameter.to_owned());
+                }
+            }
+            Ok(59) => {
+                if depth >= ColorDepth::Ansi256 {
+                    kept.push(parameter.to_owned());
+                }
+            }
+            Ok(code @ 38) | Ok(code @ 48) | Ok(code @ 58) => {
+                let color = match parameter.contains(':') {
+                    true => parse_extended_color(subparameters, true),
+                    false => parse_extended_color(&mut parameters, false),
+                };
+                if let Some(color) = color.and_then(|c| downsample_color(code, &c, depth)) {
+                    kept.push(color);
+                }
+            }
+            _ => kept.push(parameter.to_owned()),
+        }
+    }
+
+    // Leaving out the escape entirely keeps it from turning into a reset.
+    if !kept.is_empty() {
+        out.extend_from_slice(format!(csi!("{}m"), kept.join(";")).as_bytes());
+    }
+}
+
+/// Parses what follows 38, 48 or 58 in an SGR. With colons, direct colors can have a color space
+/// ID before their channels, which is ignored.
+fn parse_extended_color<'a, I>(mut parameters: I, colons: bool) -> Option<ExtendedColor>
+where
+    I: Iterator<Item = &'a str>,
+{
+    match parameters.next()? {
+        "5" => Some(ExtendedColor::ColorTable(ColorTableValue(
+            parameters.next()?.parse().ok()?,
+        ))),
+        "2" => {
+            // Taking any more than the channels from semicolon-separated parameters would
+            // swallow the ones after the color.
+            let mut channels: Vec<_> = parameters.take(if colons { 4 } else { 3 }).collect();
+            if channels.len() == 4 {
+                channels.remove(0);
+            }
+            match channels[..] {
+                [r, g, b] => Some(ExtendedColor::Rgb(Rgb(
+                    r.parse().ok()?,
+                    g.parse().ok()?,
+                    b.parse().ok()?,
+                ))),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Writes `color` for the SGR `code` that introduced it as parameters that fit in `depth`, if
+/// there are any.
+fn downsample_color(code: u8, color: &ExtendedColor, depth: ColorDepth) -> Option<String> {
+    match (depth, color) {
+        (ColorDepth::Monochrome, _) => None,
+        (ColorDepth::Ansi16, _) if code == 58 => None,
+        (ColorDepth::Ansi16, color) => {
+            let i = match color {
+                ExtendedColor::ColorTable(c) => c.preset_index(),
+                ExtendedColor::Rgb(rgb) => rgb.preset_index(),
+            };
+            // 38 and 48 go to 30 and 40 for normal colors, and 90 and 100 for bright ones.
+            let base = match i {
+                0..=7 => code - 8,
+                _ => code + 52,
+            };
+            Some((base + i % 8).to_string())
+        }
+        (_, ExtendedColor::ColorTable(ColorTableValue(v))) => Some(format!("{};5;{}", code, v)),
+        (ColorDepth::Ansi256, ExtendedColor::Rgb(rgb)) => {
+            Some(format!("{};5;{}", code, rgb.to_color_table_value().0))
+        }
+        (ColorDepth::TrueColor, ExtendedColor::Rgb(Rgb(r, g, b))) => {
+            Some(format!("{};2;{};{};{}", code, r, g, b))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted<T: TerminalOutput>(t: &T) -> String {
+        let mut out = Vec::new();
+        t.fmt(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn style_keeps_bright_when_not_faint() {
+        let style = Style {
+            bright: true,
+            ..Style::default()
+        };
+        let intensity = |style: &Style| {
+            formatted(style)
+                .split("\x1B[23m")
+                .next()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(intensity(&style), "\x1B[22m\x1B[1m");
+        assert_eq!(
+            intensity(&Style {
+                bright: false,
+                ..style.clone()
+            }),
+            "\x1B[22m"
+        );
+        assert_eq!(
+            intensity(&Style {
+                bright: false,
+                faint: true,
+                ..style
+            }),
+            "\x1B[22m\x1B[2m"
+        );
+    }
+
+    #[test]
+    fn style_sets_or_clears_every_attribute() {
+        assert_eq!(
+            formatted(&Style::default()),
+            "\x1B[22m\x1B[23m\x1B[24m\x1B[59m\x1B[25m\x1B[27m\x1B[28m\x1B[29m\x1B[55m"
+        );
+        let font = FontSpec {
+            style: Style {
+                faint: true,
+                crossed_out: true,
+                blink: Some(BlinkSpeed::Rapid),
+                ..Style::default()
+            },
+            foreground_color: ColorSpec::Preset(PresetColorSpec {
+                color: PresetColor::Red,
+                bright: false,
+            }),
+            background_color: ColorSpec::Extended(ExtendedColor::ColorTable(ColorTableValue(236))),
+        };
+        assert_eq!(
+            formatted(&font),
+            "\x1B[22m\x1B[2m\x1B[23m\x1B[24m\x1B[59m\x1B[6m\x1B[27m\x1B[28m\x1B[9m\x1B[55m\
+             \x1B[31m\x1B[48;5;236m"
+        );
+    }
+
+    #[test]
+    fn styled_underlines() {
+        use self::{SetGraphicsRenditionEscape::*, UnderlineStyle::*};
+        let styles = [
+            (Single, 1),
+            (Double, 2),
+            (Curly, 3),
+            (Dotted, 4),
+            (Dashed, 5),
+        ];
+        for &(style, code) in &styles {
+            assert_eq!(
+                formatted(&StyledUnderline(style)),
+                format!("\x1B[4:{}m", code)
+            );
+        }
+        assert_eq!(
+            formatted(&UnderlineColor(ExtendedColor::ColorTable(ColorTableValue(
+                196
+            )))),
+            "\x1B[58;5;196m"
+        );
+        assert_eq!(
+            formatted(&UnderlineColor(ExtendedColor::Rgb(Rgb(255, 128, 0)))),
+            "\x1B[58;2;255;128;0m"
+        );
+        assert_eq!(formatted(&UnderlineColorDefault), "\x1B[59m");
+    }
+
+    #[test]
+    fn style_underlines() {
+        let underline = |style: &Style| {
+            let formatted = formatted(style);
+            let start = formatted.find("\x1B[23m").unwrap() + "\x1B[23m".len();
+            let end = formatted.find("\x1B[25m").unwrap();
+            formatted[start..end].to_string()
+        };
+        let squiggle = Style {
+            underline: Some(UnderlineStyle::Curly),
+            underline_color: Some(ExtendedColor::Rgb(Rgb(255, 0, 0))),
+            ..Style::default()
+        };
+        assert_eq!(underline(&squiggle), "\x1B[4:3m\x1B[58;2;255;0;0m");
+        // Plain underlines use the escape that every terminal understands.
+        let plain = Style {
+            underline: Some(UnderlineStyle::Single),
+            ..Style::default()
+        };
+        assert_eq!(underline(&plain), "\x1B[4m\x1B[59m");
+        assert_eq!(underline(&Style::default()), "\x1B[24m\x1B[59m");
+    }
+
+    fn downsampled(formatted: &str, depth: ColorDepth) -> String {
+        let mut out = Vec::new();
+        fmt_downsampled(&format_args!("{}", formatted), depth, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn downsamples_true_color() {
+        let red = "\x1B[38;2;255;0;0m";
+        assert_eq!(downsampled(red, ColorDepth::TrueColor), red);
+        assert_eq!(downsampled(red, ColorDepth::Ansi256), "\x1B[38;5;196m");
+        assert_eq!(downsampled(red, ColorDepth::Ansi16), "\x1B[91m");
+        assert_eq!(downsampled(red, ColorDepth::Monochrome), "");
+        assert_eq!(
+            downsampled("\x1B[48;2;200;10;10m", ColorDepth::Ansi16),
+            "\x1B[41m"
+        );
+    }
+
+    #[test]
+    fn downsamples_color_table() {
+        let color = "\x1B[48;5;17m";
+        assert_eq!(downsampled(color, ColorDepth::Ansi256), color);
+        assert_eq!(downsampled(color, ColorDepth::Ansi16), "\x1B[40m");
+        assert_eq!(downsampled("\x1B[38;5;12m", ColorDepth::Ansi16), "\x1B[94m");
+    }
+
+    #[test]
+    fn keeps_parameters_after_extended_colors() {
+        assert_eq!(
+            downsampled("\x1B[38;2;255;0;0;1m", ColorDepth::Ansi256),
+            "\x1B[38;5;196;1m"
+        );
+        assert_eq!(
+            downsampled("\x1B[38;2;255;0;0;48;5;17m", ColorDepth::Ansi256),
+            "\x1B[38;5;196;48;5;17m"
+        );
+        assert_eq!(
+            downsampled("\x1B[1;48;5;17;38;2;255;0;0;4m", ColorDepth::Ansi16),
+            "\x1B[1;40;91;4m"
+        );
+    }
+
+    #[test]
+    fn downsamples_colon_subparameters() {
+        assert_eq!(
+            downsampled("\x1B[38:2::255:0:0;1m", ColorDepth::Ansi256),
+            "\x1B[38;5;196;1m"
+        );
+        assert_eq!(
+            downsampled("\x1B[38:2:255:0:0;1m", ColorDepth::Ansi256),
+            "\x1B[38;5;196;1m"
+        );
+        assert_eq!(
+            downsampled("\x1B[4:3;48:5:17m", ColorDepth::Ansi16),
+            "\x1B[4:3;40m"
+        );
+    }
+
+    #[test]
+    fn downsamples_underline_colors() {
+        assert_eq!(
+            downsampled("\x1B[58;2;255;0;0m", ColorDepth::Ansi256),
+            "\x1B[58;5;196m"
+        );
+        assert_eq!(
+            downsampled("\x1B[58:2::255:0:0m", ColorDepth::Ansi256),
+            "\x1B[58;5;196m"
+        );
+        // The 16 colors have no underline color of their own.
+        assert_eq!(downsampled("\x1B[4;58;5;9m", ColorDepth::Ansi16), "\x1B[4m");
+        assert_eq!(downsampled("\x1B[59m", ColorDepth::Ansi256), "\x1B[59m");
+        assert_eq!(downsampled("\x1B[59m", ColorDepth::Ansi16), "");
+    }
+
+    #[test]
+    fn monochrome_strips_colors() {
+        assert_eq!(
+            downsampled(
+                "a\x1B[1;31;38;5;100;4mb\x1B[42mc\x1B[39;49md\x1B[0m\x1B[m",
+                ColorDepth::Monochrome
+            ),
+            "a\x1B[1;4mbcd\x1B[0m\x1B[m"
+        );
+    }
+
+    #[test]
+    fn leaves_other_escapes_alone() {
+        let other = "\x1B[2J\x1B[>4;2m\x1B[?25l\x1B]8;;https://example.com\x1B\\\x1B[";
+        assert_eq!(downsampled(other, ColorDepth::Monochrome), other);
+    }
+
+    #[test]
+    fn nearest_color_table_value() {
+        assert_eq!(Rgb(255, 0, 0).to_color_table_value().0, 196);
+        assert_eq!(Rgb(95, 135, 175).to_color_table_value().0, 67);
+        assert_eq!(Rgb(128, 128, 128).to_color_table_value().0, 244);
+        assert_eq!(Rgb(250, 5, 3).to_color_table_value().0, 196);
+    }
+
+    #[test]
+    fn nearest_preset_color() {
+        use self::PresetColor::*;
+        let is = |spec: PresetColorSpec, expected: PresetColor, bright: bool| {
+            std::mem::discriminant(&spec.color) == std::mem::discriminant(&expected)
+                && spec.bright == bright
+        };
+        assert!(is(Rgb(255, 0, 0).to_preset_color_spec(), Red, true));
+        assert!(is(Rgb(200, 10, 10).to_preset_color_spec(), Red, false));
+        assert!(is(Rgb(92, 92, 255).to_preset_color_spec(), Blue, true));
+        assert!(is(Rgb(10, 10, 10).to_preset_color_spec(), Black, false));
+        assert!(is(ColorTableValue(196).to_preset_color_spec(), Red, true));
+        assert!(is(ColorTableValue(4).to_preset_color_spec(), Blue, false));
+        assert!(is(
+            ColorTableValue(255).to_preset_color_spec(),
+            White,
+            false
+        ));
+    }
+}
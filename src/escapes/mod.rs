@@ -1,10 +1,19 @@
 pub mod cursor;
+pub mod erase;
 pub mod formatting;
+pub mod mouse;
+pub mod osc;
 
 use {
-    self::{cursor::CursorEscape, formatting::SetGraphicsRenditionEscape},
+    self::{
+        cursor::CursorEscape,
+        erase::{DisplayErasure, LineErasure},
+        formatting::SetGraphicsRenditionEscape,
+        mouse::{MouseEncoding, MouseTrackingMode},
+        osc::OscEscape,
+    },
     crate::TerminalOutput,
-    std::io,
+    std::io::{self, Write},
 };
 
 /// Represents the full set of ANSI escapes that are supported cross-platform by this library.
@@ -17,9 +26,65 @@ pub enum AnsiEscape {
     ScrollDown(u16),
     InsertLine(u16),
     DeleteLine(u16),
+    EraseInDisplay(DisplayErasure),
+    EraseInLine(LineErasure),
+    /// Erases this many characters from the cursor onwards, without moving anything.
+    EraseCharacters(u16),
+    /// Inserts this many blank characters at the cursor, moving the rest of the line right.
+    InsertCharacters(u16),
+    /// Deletes this many characters at the cursor, moving the rest of the line left.
+    DeleteCharacters(u16),
+    /// Confines scrolling to the lines from `top` to `bottom`, inclusive and counted from 1, so
+    /// that the lines outside of them stay put. Also moves the cursor to the top left corner.
+    SetScrollingRegion {
+        top: u16,
+        bottom: u16,
+    },
+    /// Lets the whole display scroll again.
+    ResetScrollingRegion,
+    /// Confines scrolling to the columns from `left` to `right`, inclusive and counted from 1.
+    /// Only has an effect while `EnableLeftRightMarginMode` is in effect.
+    SetLeftRightMargins {
+        left: u16,
+        right: u16,
+    },
+    /// Allows setting left and right margins with `SetLeftRightMargins`. While this is in effect,
+    /// `CursorEscape::SavePosition` resets the margins instead of saving the cursor position.
+    EnableLeftRightMarginMode,
+    DisableLeftRightMarginMode,
     SetGraphicsRendition(SetGraphicsRenditionEscape),
     SwitchToAlternateScreenBuffer,
     SwitchToMainScreenBuffer,
+    EnableMouseTracking(MouseTrackingMode),
+    DisableMouseTracking(MouseTrackingMode),
+    EnableMouseEncoding(MouseEncoding),
+    DisableMouseEncoding(MouseEncoding),
+    EnableBracketedPaste,
+    DisableBracketedPaste,
+    EnableFocusReporting,
+    DisableFocusReporting,
+    /// Makes the terminal hold off on showing output until `EndSynchronizedUpdate`, so that a
+    /// frame is never shown half-drawn. See `AnsiTerminal::frame`.
+    BeginSynchronizedUpdate,
+    EndSynchronizedUpdate,
+    /// Asks the terminal to report the size of its text area, in characters, as input.
+    QueryTextAreaSize,
+    /// Asks the terminal to report its conformance level and features as input. Every terminal
+    /// answers this. See `AnsiTerminal::query_primary_device_attributes`.
+    QueryPrimaryDeviceAttributes,
+    /// Asks the terminal to report its type and version as input. See
+    /// `AnsiTerminal::query_secondary_device_attributes`.
+    QuerySecondaryDeviceAttributes,
+    /// Asks the terminal to report its name and version (XTVERSION) as input. See
+    /// `AnsiTerminal::query_terminal_version`.
+    QueryTerminalVersion,
+    /// Asks the terminal to report whether the private mode with this number is set (DECRQM) as
+    /// input, like 2026 for `BeginSynchronizedUpdate`. See `AnsiTerminal::query_private_mode`.
+    QueryPrivateMode(u16),
+    Osc(OscEscape),
+    /// Saves the window title and icon name on a stack, to be restored by `PopTitle`.
+    PushTitle,
+    PopTitle,
 }
 
 impl TerminalOutput for AnsiEscape {
@@ -40,9 +105,77 @@ impl TerminalOutput for AnsiEscape {
             ScrollDown(x) => write_csi!("{}T"; x),
             InsertLine(x) => write_csi!("{}L"; x),
             DeleteLine(x) => write_csi!("{}M"; x),
+            EraseInDisplay(e) => write_csi!("{}J"; e.parameter()),
+            EraseInLine(e) => write_csi!("{}K"; e.parameter()),
+            EraseCharacters(x) => write_csi!("{}X"; x),
+            InsertCharacters(x) => write_csi!("{}@"; x),
+            DeleteCharacters(x) => write_csi!("{}P"; x),
+            SetScrollingRegion { top, bottom } => write_csi!("{};{}r"; top, bottom),
+            ResetScrollingRegion => write_csi!("r";),
+            SetLeftRightMargins { left, right } => write_csi!("{};{}s"; left, right),
+            EnableLeftRightMarginMode => write_csi!("?69h";),
+            DisableLeftRightMarginMode => write_csi!("?69l";),
             SetGraphicsRendition(sgr) => TerminalOutput::fmt(sgr, f),
             SwitchToAlternateScreenBuffer => write_csi!("?1049h";),
             SwitchToMainScreenBuffer => write_csi!("?1049l";),
+            EnableMouseTracking(m) => write_csi!("?{}h"; m.private_mode()),
+            DisableMouseTracking(m) => write_csi!("?{}l"; m.private_mode()),
+            EnableMouseEncoding(e) => write_csi!("?{}h"; e.private_mode()),
+            DisableMouseEncoding(e) => write_csi!("?{}l"; e.private_mode()),
+            EnableBracketedPaste => write_csi!("?2004h";),
+            DisableBracketedPaste => write_csi!("?2004l";),
+            EnableFocusReporting => write_csi!("?1004h";),
+            DisableFocusReporting => write_csi!("?1004l";),
+            BeginSynchronizedUpdate => write_csi!("?2026h";),
+            EndSynchronizedUpdate => write_csi!("?2026l";),
+            QueryTextAreaSize => write_csi!("18t";),
+            QueryPrimaryDeviceAttributes => write_csi!("c";),
+            QuerySecondaryDeviceAttributes => write_csi!(">c";),
+            QueryTerminalVersion => write_csi!(">q";),
+            QueryPrivateMode(x) => write_csi!("?{}$p"; x),
+            Osc(o) => TerminalOutput::fmt(o, f),
+            PushTitle => write_csi!("22;0t";),
+            PopTitle => write_csi!("23;0t";),
         }
     }
 }
+
+/// Watches the bytes written to a terminal for escapes that matter later, like ones that have to
+/// be undone when the terminal is dropped. Escapes can be split across writes, so implementations
+/// keep track of how far into one the bytes seen so far got.
+pub(crate) trait Observe {
+    fn observe(&mut self, bytes: &[u8]);
+
+    /// Wraps `writer` so that everything written through it is observed.
+    fn track<'a, W: Write>(&'a mut self, writer: &'a mut W) -> TrackedWriter<'a, Self, W>
+    where
+        Self: Sized,
+    {
+        TrackedWriter {
+            writer,
+            observer: self,
+        }
+    }
+}
+
+/// Watches for nothing, for when there's nothing more to watch for.
+impl Observe for () {
+    fn observe(&mut self, _: &[u8]) {}
+}
+
+pub(crate) struct TrackedWriter<'a, O, W> {
+    writer: &'a mut W,
+    observer: &'a mut O,
+}
+
+impl<'a, O: Observe, W: Write> Write for TrackedWriter<'a, O, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.observer.observe(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,45 @@
+/// Represents which mouse events a terminal reports once mouse tracking has been enabled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MouseTrackingMode {
+    /// Only button presses, with no modifiers or releases.
+    X10,
+    /// Button presses and releases, along with the scroll wheel.
+    Normal,
+    /// Like `Normal`, but also reports motion while a button is held down.
+    ButtonEvent,
+    /// Like `ButtonEvent`, but reports all motion, even with no buttons held.
+    AnyEvent,
+}
+
+impl MouseTrackingMode {
+    pub(crate) fn private_mode(self) -> u16 {
+        use self::MouseTrackingMode::*;
+        match self {
+            X10 => 9,
+            Normal => 1000,
+            ButtonEvent => 1002,
+            AnyEvent => 1003,
+        }
+    }
+}
+
+/// Represents an alternative encoding for mouse reports. Without one, coordinates past column or
+/// row 223 cannot be reported.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MouseEncoding {
+    /// `ESC[<b;x;yM`, which also distinguishes which button was released. Preferred where
+    /// supported.
+    Sgr,
+    /// `ESC[b;x;yM`, as introduced by urxvt.
+    Urxvt,
+}
+
+impl MouseEncoding {
+    pub(crate) fn private_mode(self) -> u16 {
+        use self::MouseEncoding::*;
+        match self {
+            Sgr => 1006,
+            Urxvt => 1015,
+        }
+    }
+}
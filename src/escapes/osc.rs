@@ -0,0 +1,326 @@
+use {crate::TerminalOutput, std::io};
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Represents the two ways an OSC escape can be terminated. Terminals that don't understand the
+/// one used may swallow everything that follows the escape, so each `OscEscape` defaults to the
+/// one that's most widely supported for it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OscTerminator {
+    /// `BEL`, which xterm introduced and most terminals accept.
+    Bell,
+    /// `ESC \`, which is what the standard specifies.
+    StringTerminator,
+}
+
+impl TerminalOutput for OscTerminator {
+    fn fmt(&self, f: &mut io::Write) -> io::Result<()> {
+        use self::OscTerminator::*;
+        match self {
+            Bell => write!(f, "\x07"),
+            StringTerminator => write!(f, ansi!("\\")),
+        }
+    }
+}
+
+/// Represents Operating System Command escapes, which talk to the program hosting the terminal
+/// rather than the terminal's display.
+#[derive(Clone, Debug)]
+pub enum OscEscape {
+    /// Sets the title of the terminal window. Control characters are left out, since they could
+    /// end the escape early.
+    SetWindowTitle(String),
+    /// Sets the name of the terminal window when it's minimized. Control characters are left out,
+    /// since they could end the escape early.
+    SetIconName(String),
+    /// Makes the text written after this, up to `CloseHyperlink`, a link to `uri`. Terminals that
+    /// see separate links with the same `id` treat them as one, like when a link is wrapped
+    /// across lines. Bytes of `uri` outside of printable ASCII are percent-encoded, and
+    /// characters that aren't allowed in `id` are left out.
+    OpenHyperlink {
+        uri: String,
+        id: Option<String>,
+    },
+    CloseHyperlink,
+    /// Copies `content` to one of the clipboards of the machine the terminal runs on, even over
+    /// SSH. Not every terminal allows this.
+    SetClipboard {
+        target: ClipboardTarget,
+        content: String,
+    },
+    /// Asks the terminal to report what's on one of its clipboards as input. See
+    /// `AnsiTerminal::query_clipboard`. Fewer terminals allow this than `SetClipboard`.
+    QueryClipboard(ClipboardTarget),
+}
+
+impl OscEscape {
+    /// The terminator that this escape is written with by default.
+    pub fn preferred_terminator(&self) -> OscTerminator {
+        use self::OscEscape::*;
+        match self {
+            // Terminals that only ever supported titles know nothing but the original BEL.
+            SetWindowTitle(_) | SetIconName(_) => OscTerminator::Bell,
+            OpenHyperlink { .. } | CloseHyperlink => OscTerminator::StringTerminator,
+            // This is what xterm replies to queries with, so it must understand it.
+            SetClipboard { .. } | QueryClipboard(_) => OscTerminator::Bell,
+        }
+    }
+
+    /// Writes this escape, terminated with `terminator` instead of the preferred one.
+    pub fn fmt_terminated(&self, f: &mut io::Write, terminator: OscTerminator) -> io::Result<()> {
+        use self::OscEscape::*;
+        match self {
+            SetWindowTitle(title) => {
+                write!(f, osc!("2;"))?;
+                write_sanitized(f, title)?;
+            }
+            SetIconName(name) => {
+                write!(f, osc!("1;"))?;
+                write_sanitized(f, name)?;
+            }
+            OpenHyperlink { uri, id } => {
+                write!(f, osc!("8;"))?;
+                if let Some(id) = id {
+                    // Parameters are separated by colons, and a semicolon ends them.
+                    write!(f, "id=")?;
+                    for part in id.split(|c: char| c.is_control() || c == ':' || c == ';') {
+                        write!(f, "{}", part)?;
+                    }
+                }
+                write!(f, ";")?;
+                write_percent_encoded(f, uri)?;
+            }
+            CloseHyperlink => write!(f, osc!("8;;"))?,
+            SetClipboard { target, content } => {
+                write!(f, osc!("52;{};"), target.parameter())?;
+                f.write_all(&encode_base64(content.as_bytes()))?;
+            }
+            QueryClipboard(target) => write!(f, osc!("52;{};?"), target.parameter())?,
+        }
+        TerminalOutput::fmt(&terminator, f)
+    }
+}
+
+impl TerminalOutput for OscEscape {
+    fn fmt(&self, f: &mut io::Write) -> io::Result<()> {
+        self.fmt_terminated(f, self.preferred_terminator())
+    }
+}
+
+/// An `OscEscape` written with a specific terminator rather than its preferred one.
+#[derive(Clone, Debug)]
+pub struct TerminatedOsc(pub OscEscape, pub OscTerminator);
+
+impl TerminalOutput for TerminatedOsc {
+    fn fmt(&self, f: &mut io::Write) -> io::Result<()> {
+        let TerminatedOsc(escape, terminator) = self;
+        escape.fmt_terminated(f, *terminator)
+    }
+}
+
+/// Represents the clipboards that `OscEscape::SetClipboard` can copy to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClipboardTarget {
+    /// The clipboard that copying and pasting usually goes through
+    Clipboard,
+    /// The X11 primary selection, which is usually pasted with the middle mouse button
+    Primary,
+}
+
+impl ClipboardTarget {
+    fn parameter(self) -> char {
+        use self::ClipboardTarget::*;
+        match self {
+            Clipboard => 'c',
+            Primary => 'p',
+        }
+    }
+}
+
+/// Writes `text` as a link to `url`, for terminals that support it. Others just show `text`.
+#[derive(Clone, Debug)]
+pub struct Hyperlink {
+    pub url: String,
+    pub text: String,
+}
+
+impl TerminalOutput for Hyperlink {
+    fn fmt(&self, f: &mut io::Write) -> io::Result<()> {
+        let Hyperlink { url, text } = self;
+        TerminalOutput::fmt(
+            &OscEscape::OpenHyperlink {
+                uri: url.clone(),
+                id: None,
+            },
+            f,
+        )?;
+        write!(f, "{}", text)?;
+        TerminalOutput::fmt(&OscEscape::CloseHyperlink, f)
+    }
+}
+
+/// Writes `text` without any of the control characters in it, including the 8-bit ones that some
+/// terminals also treat as terminators.
+fn write_sanitized(f: &mut io::Write, text: &str) -> io::Result<()> {
+    for part in text.split(char::is_control) {
+        write!(f, "{}", part)?;
+    }
+    Ok(())
+}
+
+/// Writes `uri` with every byte outside of printable ASCII percent-encoded, which is all that
+/// terminals accept in hyperlinks.
+fn write_percent_encoded(f: &mut io::Write, uri: &str) -> io::Result<()> {
+    for &b in uri.as_bytes() {
+        match b {
+            0x20..=0x7E => f.write_all(&[b])?,
+            _ => write!(f, "%{:02X}", b)?,
+        }
+    }
+    Ok(())
+}
+
+fn encode_base64(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &b)| group | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            encoded.push(match i <= chunk.len() {
+                true => BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize],
+                false => b'=',
+            });
+        }
+    }
+    encoded
+}
+
+/// Decodes standard base64 with optional padding, or returns `None` if `encoded` isn't valid.
+pub(crate) fn decode_base64(encoded: &[u8]) -> Option<Vec<u8>> {
+    let encoded = match encoded.iter().position(|&b| b == b'=') {
+        Some(padding) if encoded[padding..].iter().all(|&b| b == b'=') => &encoded[..padding],
+        Some(_) => return None,
+        None => encoded,
+    };
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3 + 2);
+    for chunk in encoded.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut group = 0u32;
+        for (i, &b) in chunk.iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|&a| a == b)?;
+            group |= (value as u32) << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            decoded.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted(escape: &OscEscape) -> String {
+        let mut out = Vec::new();
+        escape.fmt(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn hyperlink_uri_is_percent_encoded() {
+        let link = OscEscape::OpenHyperlink {
+            uri: "https://example.com/a b/café?x=\x1B\x07".to_string(),
+            id: Some("one;two:\x07three".to_string()),
+        };
+        assert_eq!(
+            formatted(&link),
+            "\x1B]8;id=onetwothree;https://example.com/a b/caf%C3%A9?x=%1B%07\x1B\\"
+        );
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        let cases: &[(&[u8], &[u8])] = &[
+            (b"", b""),
+            (b"f", b"Zg=="),
+            (b"fo", b"Zm8="),
+            (b"foo", b"Zm9v"),
+            (b"foob", b"Zm9vYg=="),
+            (b"fooba", b"Zm9vYmE="),
+            (b"foobar", b"Zm9vYmFy"),
+            (b"\x00\xFF\xFE", b"AP/+"),
+        ];
+        for &(plain, encoded) in cases {
+            assert_eq!(encode_base64(plain), encoded);
+            assert_eq!(decode_base64(encoded).as_deref(), Some(plain));
+        }
+    }
+
+    #[test]
+    fn base64_without_padding() {
+        assert_eq!(decode_base64(b"Zg").as_deref(), Some(&b"f"[..]));
+        assert_eq!(decode_base64(b"Zm8").as_deref(), Some(&b"fo"[..]));
+    }
+
+    #[test]
+    fn invalid_base64() {
+        for encoded in [
+            &b"Z"[..],
+            b"Zm9vY",
+            b"Zm9v!A==",
+            b"Zg==Zg==",
+            b"Z=g=",
+            b"Zm 9v",
+        ] {
+            assert_eq!(decode_base64(encoded), None, "{:?}", encoded);
+        }
+    }
+
+    #[test]
+    fn titles_leave_out_control_characters() {
+        let title = "a\x07b\x1B\\c\u{9C}d\u{9D}e\nf".to_string();
+        assert_eq!(
+            formatted(&OscEscape::SetWindowTitle(title.clone())),
+            "\x1B]2;ab\\cdef\x07"
+        );
+        assert_eq!(
+            formatted(&OscEscape::SetIconName(title)),
+            "\x1B]1;ab\\cdef\x07"
+        );
+    }
+
+    #[test]
+    fn terminators() {
+        let title = OscEscape::SetWindowTitle("title".to_string());
+        assert_eq!(title.preferred_terminator(), OscTerminator::Bell);
+        let mut out = Vec::new();
+        TerminatedOsc(title, OscTerminator::StringTerminator)
+            .fmt(&mut out)
+            .unwrap();
+        assert_eq!(out, b"\x1B]2;title\x1B\\");
+        let query = OscEscape::QueryClipboard(ClipboardTarget::Clipboard);
+        let mut out = Vec::new();
+        TerminatedOsc(query, OscTerminator::Bell)
+            .fmt(&mut out)
+            .unwrap();
+        assert_eq!(out, b"\x1B]52;c;?\x07");
+    }
+
+    #[test]
+    fn title_stack() {
+        use crate::escapes::AnsiEscape::*;
+        let formatted = |escape| {
+            let mut out = Vec::new();
+            TerminalOutput::fmt(&escape, &mut out).unwrap();
+            out
+        };
+        assert_eq!(formatted(PushTitle), b"\x1B[22;0t");
+        assert_eq!(formatted(PopTitle), b"\x1B[23;0t");
+    }
+}
@@ -0,0 +1,876 @@
+use {
+    super::{
+        keyboard::{KeyCode, KeyEvent, KeyModifiers},
+        mouse::MouseEvent,
+        Decoded, Event, Reply,
+    },
+    crate::{
+        capabilities::{ModeState, PrimaryDeviceAttributes, SecondaryDeviceAttributes},
+        escapes::osc::decode_base64,
+    },
+    std::str,
+};
+
+const BEL: u8 = 0x07;
+const ESC: u8 = 0x1B;
+const PASTE_START: &[u8] = b"\x1B[200~";
+const PASTE_END: &[u8] = b"\x1B[201~";
+const CLIPBOARD_REPORT_START: &[u8] = b"\x1B]52;";
+
+/// Incrementally decodes the bytes read from a terminal's input stream into `Event`s and
+/// `Reply`s.
+#[derive(Debug, Default)]
+pub(crate) struct EventDecoder {
+    buffer: Vec<u8>,
+    /// Whether a cursor position report is expected, which `ESC[1;5R` could be just as well as
+    /// Ctrl+F3.
+    pub(crate) awaiting_cursor_position: bool,
+}
+
+enum Parse {
+    /// An item was decoded from the given number of bytes. Recognized sequences that carry no
+    /// event for the application are consumed with `None`.
+    Complete(usize, Option<Decoded>),
+    /// More bytes are needed to decide what the buffered input means.
+    Incomplete,
+}
+
+impl EventDecoder {
+    pub(crate) fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Whether there are buffered bytes that could not be decoded yet.
+    pub(crate) fn has_pending_input(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// Decodes the next item from the buffered input. If `force` is set, input that is still
+    /// ambiguous (like a lone ESC that may or may not start an escape sequence) is resolved as
+    /// individual key presses instead of waiting for more bytes.
+    pub(crate) fn next(&mut self, force: bool) -> Option<Decoded> {
+        while !self.buffer.is_empty() {
+            let (consumed, decoded) = match parse(&self.buffer, self.awaiting_cursor_position) {
+                Parse::Complete(consumed, event) => (consumed, event),
+                // Pastes and clipboard contents can be arbitrarily long, so there's no telling when
+                // one is taking too long.
+                Parse::Incomplete
+                    if force
+                        && !self.buffer.starts_with(PASTE_START)
+                        && !self.buffer.starts_with(CLIPBOARD_REPORT_START) =>
+                {
+                    resolve_incomplete(&self.buffer)
+                }
+                Parse::Incomplete => return None,
+            };
+            self.buffer.drain(..consumed);
+            if decoded.is_some() {
+                return decoded;
+            }
+        }
+        None
+    }
+}
+
+fn event(event: Event) -> Option<Decoded> {
+    Some(Decoded::Event(event))
+}
+
+fn key(code: KeyCode, modifiers: KeyModifiers) -> Option<Decoded> {
+    event(Event::Key(KeyEvent::new(code, modifiers)))
+}
+
+fn with_alt(decoded: Option<Decoded>) -> Option<Decoded> {
+    match decoded {
+        Some(Decoded::Event(Event::Key(mut k))) => {
+            k.modifiers.alt = true;
+            event(Event::Key(k))
+        }
+        d => d,
+    }
+}
+
+fn parse(buf: &[u8], awaiting_cursor_position: bool) -> Parse {
+    match buf[0] {
+        ESC => parse_escape(buf, awaiting_cursor_position),
+        _ => parse_key(buf),
+    }
+}
+
+fn parse_escape(buf: &[u8], awaiting_cursor_position: bool) -> Parse {
+    use self::Parse::*;
+    match buf.get(1) {
+        None => Incomplete,
+        Some(b'[') => parse_csi(buf, awaiting_cursor_position),
+        Some(b'O') => parse_ss3(buf),
+        Some(b']') => parse_control_string(buf, decode_osc),
+        Some(b'P') => parse_control_string(buf, decode_dcs),
+        // Terminals send Alt+<key> as ESC followed by whatever <key> would have sent.
+        Some(_) => match parse(&buf[1..], awaiting_cursor_position) {
+            Complete(consumed, decoded) => Complete(consumed + 1, with_alt(decoded)),
+            Incomplete => Incomplete,
+        },
+    }
+}
+
+fn parse_ss3(buf: &[u8]) -> Parse {
+    use self::KeyCode::*;
+    let code = match buf.get(2) {
+        None => return Parse::Incomplete,
+        Some(b'A') => Up,
+        Some(b'B') => Down,
+        Some(b'C') => Right,
+        Some(b'D') => Left,
+        Some(b'H') => Home,
+        Some(b'F') => End,
+        Some(b'M') => Enter,
+        Some(&b) if (b'P'..=b'S').contains(&b) => F(b - b'P' + 1),
+        Some(_) => return Parse::Complete(3, None),
+    };
+    Parse::Complete(3, key(code, KeyModifiers::default()))
+}
+
+fn parse_csi(buf: &[u8], awaiting_cursor_position: bool) -> Parse {
+    if let Some(content) = buf.strip_prefix(PASTE_START) {
+        return match content
+            .windows(PASTE_END.len())
+            .position(|w| w == PASTE_END)
+        {
+            Some(end) => Parse::Complete(
+                PASTE_START.len() + end + PASTE_END.len(),
+                event(Event::Paste(
+                    String::from_utf8_lossy(&content[..end]).into_owned(),
+                )),
+            ),
+            None => Parse::Incomplete,
+        };
+    }
+    // The Linux console sends F1 through F5 as `ESC[[A` through `ESC[[E`.
+    if buf.get(2) == Some(&b'[') {
+        return match buf.get(3) {
+            None => Parse::Incomplete,
+            Some(&b) if (b'A'..=b'E').contains(&b) => {
+                Parse::Complete(4, key(KeyCode::F(b - b'A' + 1), KeyModifiers::default()))
+            }
+            Some(_) => Parse::Complete(4, None),
+        };
+    }
+    // Mouse reports in the default encoding: `ESC[M` followed by three bytes, each offset by 32.
+    if buf.get(2) == Some(&b'M') {
+        if buf.len() < 6 {
+            return Parse::Incomplete;
+        }
+        let offset = |b: u8| u16::from(b.saturating_sub(32));
+        let report = MouseEvent::from_report(offset(buf[3]), offset(buf[4]), offset(buf[5]), false);
+        return Parse::Complete(6, report.map(Event::Mouse).and_then(event));
+    }
+    for (i, &b) in buf.iter().enumerate().skip(2) {
+        match b {
+            // Parameter and intermediate bytes
+            0x20..=0x3F => continue,
+            0x40..=0x7E => {
+                let decoded = decode_csi(&buf[2..i], b, awaiting_cursor_position);
+                return Parse::Complete(i + 1, decoded);
+            }
+            // Anything else means the sequence was cut off; drop what we have of it.
+            _ => return Parse::Complete(i, None),
+        }
+    }
+    Parse::Incomplete
+}
+
+/// Parses an OSC or DCS sequence, which terminals only send in reply to queries, decoding what's
+/// between the introducer and the terminator with `decode`. They can be terminated by either BEL
+/// or `ESC \`.
+fn parse_control_string(buf: &[u8], decode: fn(&[u8]) -> Option<Decoded>) -> Parse {
+    let content = &buf[2..];
+    let (end, terminator_length) = match content.iter().position(|&b| b == BEL || b == ESC) {
+        None => return Parse::Incomplete,
+        Some(i) if content[i] == BEL => (i, 1),
+        Some(i) => match content.get(i + 1) {
+            None => return Parse::Incomplete,
+            Some(b'\\') => (i, 2),
+            // Anything else means the sequence was cut off; drop what we have of it.
+            Some(_) => return Parse::Complete(2 + i, None),
+        },
+    };
+    Parse::Complete(2 + end + terminator_length, decode(&content[..end]))
+}
+
+fn decode_osc(content: &[u8]) -> Option<Decoded> {
+    let mut parameters = content.splitn(3, |&b| b == b';');
+    match (parameters.next()?, parameters.next()?, parameters.next()?) {
+        // `ESC]52;target;dataBEL`, in response to `OscEscape::QueryClipboard`
+        (b"52", _, data) => {
+            let content = String::from_utf8_lossy(&decode_base64(data)?).into_owned();
+            Some(Decoded::Reply(Reply::Clipboard(content)))
+        }
+        _ => None,
+    }
+}
+
+fn decode_dcs(content: &[u8]) -> Option<Decoded> {
+    // `ESCP>|versionESC\`, in response to `AnsiEscape::QueryTerminalVersion`
+    let version = content.strip_prefix(b">|")?;
+    let version = String::from_utf8_lossy(version).into_owned();
+    Some(Decoded::Reply(Reply::TerminalVersion(version)))
+}
+
+/// Parses semicolon-separated numeric CSI parameters. Empty parameters are read as `0`.
+/// Returns `None` for parameter strings that aren't plain numbers (private markers, sub-parameters,
+/// intermediates...).
+fn parse_parameters(parameters: &[u8]) -> Option<Vec<u16>> {
+    if parameters.is_empty() {
+        return Some(Vec::new());
+    }
+    parameters
+        .split(|&b| b == b';')
+        .map(|p| match p {
+            [] => Some(0),
+            _ => str::from_utf8(p).ok()?.parse().ok(),
+        })
+        .collect()
+}
+
+fn decode_csi(
+    parameters: &[u8],
+    final_byte: u8,
+    awaiting_cursor_position: bool,
+) -> Option<Decoded> {
+    use self::KeyCode::*;
+    match (parameters.split_first(), final_byte) {
+        (Some((b'<', parameters)), _) => return decode_sgr_mouse(parameters, final_byte),
+        (Some((b'?', parameters)), b'c') => return decode_primary_device_attributes(parameters),
+        (Some((b'>', parameters)), b'c') => {
+            return decode_secondary_device_attributes(parameters);
+        }
+        (Some((b'?', parameters)), b'y') => return decode_private_mode_report(parameters),
+        _ => (),
+    }
+    let parameters = parse_parameters(parameters)?;
+    if let (b'M', &[code, column, row]) = (final_byte, parameters.as_slice()) {
+        return MouseEvent::from_report(code.saturating_sub(32), column, row, false)
+            .map(Event::Mouse)
+            .and_then(event);
+    }
+    match (final_byte, parameters.as_slice()) {
+        // Ctrl+F3 and friends look just like a report of the cursor being in the first row.
+        (b'R', &[row, column]) if awaiting_cursor_position || row != 1 => {
+            return Some(Decoded::Reply(Reply::CursorPosition { row, column }));
+        }
+        (b't', &[8, rows, columns]) => {
+            return Some(Decoded::Reply(Reply::TextAreaSize { rows, columns }));
+        }
+        _ => (),
+    }
+    let modifiers = parameters.get(1).map_or_else(KeyModifiers::default, |&p| {
+        KeyModifiers::from_xterm_parameter(p)
+    });
+    let code = match final_byte {
+        b'I' if parameters.is_empty() => return event(Event::FocusGained),
+        b'O' if parameters.is_empty() => return event(Event::FocusLost),
+        b'A' => Up,
+        b'B' => Down,
+        b'C' => Right,
+        b'D' => Left,
+        b'H' => Home,
+        b'F' => End,
+        b'P'..=b'S' => F(final_byte - b'P' + 1),
+        b'Z' => return key(Tab, KeyModifiers::SHIFT),
+        b'~' => match parameters.first()? {
+            1 | 7 => Home,
+            2 => Insert,
+            3 => Delete,
+            4 | 8 => End,
+            5 => PageUp,
+            6 => PageDown,
+            n @ 11..=15 => F((n - 10) as u8),
+            n @ 17..=21 => F((n - 11) as u8),
+            n @ 23..=26 => F((n - 12) as u8),
+            n @ 28..=29 => F((n - 13) as u8),
+            n @ 31..=34 => F((n - 14) as u8),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    key(code, modifiers)
+}
+
+/// Decodes a mouse report in the SGR encoding, i.e., `ESC[<b;x;yM`, or `ESC[<b;x;ym` for
+/// releases.
+fn decode_sgr_mouse(parameters: &[u8], final_byte: u8) -> Option<Decoded> {
+    let released = match final_byte {
+        b'M' => false,
+        b'm' => true,
+        _ => return None,
+    };
+    match parse_parameters(parameters)?.as_slice() {
+        &[code, column, row] => MouseEvent::from_report(code, column, row, released)
+            .map(Event::Mouse)
+            .and_then(event),
+        _ => None,
+    }
+}
+
+/// Decodes `ESC[?level;attributes...c`, in response to `AnsiEscape::QueryPrimaryDeviceAttributes`.
+fn decode_primary_device_attributes(parameters: &[u8]) -> Option<Decoded> {
+    let parameters = parse_parameters(parameters)?;
+    let (&conformance_level, attributes) = parameters.split_first()?;
+    Some(Decoded::Reply(Reply::PrimaryDeviceAttributes(
+        PrimaryDeviceAttributes {
+            conformance_level,
+            attributes: attributes.to_vec(),
+        },
+    )))
+}
+
+/// Decodes `ESC[>type;version;0c`, in response to `AnsiEscape::QuerySecondaryDeviceAttributes`.
+fn decode_secondary_device_attributes(parameters: &[u8]) -> Option<Decoded> {
+    match parse_parameters(parameters)?.as_slice() {
+        &[terminal_type, version, ..] => Some(Decoded::Reply(Reply::SecondaryDeviceAttributes(
+            SecondaryDeviceAttributes {
+                terminal_type,
+                version,
+            },
+        ))),
+        _ => None,
+    }
+}
+
+/// Decodes `ESC[?mode;state$y`, in response to `AnsiEscape::QueryPrivateMode`.
+fn decode_private_mode_report(parameters: &[u8]) -> Option<Decoded> {
+    match parse_parameters(parameters.strip_suffix(b"$")?)?.as_slice() {
+        &[mode, state] => {
+            let state = match state {
+                1 => ModeState::Set,
+                2 => ModeState::Reset,
+                3 => ModeState::PermanentlySet,
+                4 => ModeState::PermanentlyReset,
+                _ => ModeState::NotRecognized,
+            };
+            Some(Decoded::Reply(Reply::PrivateMode { mode, state }))
+        }
+        _ => None,
+    }
+}
+
+fn parse_key(buf: &[u8]) -> Parse {
+    use self::KeyCode::*;
+    let none = KeyModifiers::default();
+    let (code, modifiers) = match buf[0] {
+        b'\r' | b'\n' => (Enter, none),
+        b'\t' => (Tab, none),
+        0x08 | 0x7F => (Backspace, none),
+        0x00 => (Char(' '), KeyModifiers::CONTROL),
+        c @ 0x01..=0x1A => (Char((c - 0x01 + b'a') as char), KeyModifiers::CONTROL),
+        c @ 0x1C..=0x1F => (Char((c - 0x1C + b'4') as char), KeyModifiers::CONTROL),
+        first => {
+            let width = match first {
+                0x00..=0x7F => 1,
+                0xC2..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF4 => 4,
+                _ => return Parse::Complete(1, None),
+            };
+            if buf.len() < width {
+                return Parse::Incomplete;
+            }
+            match str::from_utf8(&buf[..width])
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) => return Parse::Complete(width, key(Char(c), none)),
+                None => return Parse::Complete(1, None),
+            }
+        }
+    };
+    Parse::Complete(1, key(code, modifiers))
+}
+
+/// Decides what ambiguous input means once no more bytes are coming to disambiguate it.
+fn resolve_incomplete(buf: &[u8]) -> (usize, Option<Decoded>) {
+    use self::KeyCode::*;
+    let none = KeyModifiers::default();
+    match buf {
+        [ESC] => (1, key(Esc, none)),
+        // Alt+[, Alt+O, Alt+] and Alt+Shift+P look just like the start of a CSI, SS3, OSC or DCS
+        // sequence.
+        [ESC, b, ..] if *b == b'[' || *b == b'O' || *b == b']' || *b == b'P' => {
+            (2, with_alt(key(Char(*b as char), none)))
+        }
+        [ESC, ..] => (1, key(Esc, none)),
+        // A truncated UTF-8 sequence
+        _ => (1, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::input::mouse::{MouseButton, MouseEventKind},
+    };
+
+    fn decode(input: &[u8]) -> Vec<Decoded> {
+        let mut decoder = EventDecoder::default();
+        decoder.feed(input);
+        std::iter::from_fn(|| decoder.next(true)).collect()
+    }
+
+    fn keys(input: &[u8]) -> Vec<KeyEvent> {
+        decode(input)
+            .into_iter()
+            .map(|decoded| match decoded {
+                Decoded::Event(Event::Key(k)) => k,
+                d => panic!("expected a key, got {:?}", d),
+            })
+            .collect()
+    }
+
+    fn plain(code: KeyCode) -> KeyEvent {
+        KeyEvent::from(code)
+    }
+
+    fn alt(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(
+            code,
+            KeyModifiers {
+                alt: true,
+                ..KeyModifiers::default()
+            },
+        )
+    }
+
+    #[test]
+    fn arrows_home_and_end() {
+        use self::KeyCode::*;
+        let expected = vec![
+            plain(Up),
+            plain(Down),
+            plain(Right),
+            plain(Left),
+            plain(Home),
+            plain(End),
+        ];
+        assert_eq!(keys(b"\x1B[A\x1B[B\x1B[C\x1B[D\x1B[H\x1B[F"), expected);
+        assert_eq!(keys(b"\x1BOA\x1BOB\x1BOC\x1BOD\x1BOH\x1BOF"), expected);
+        assert_eq!(
+            keys(b"\x1B[1~\x1B[4~\x1B[7~\x1B[8~"),
+            vec![plain(Home), plain(End), plain(Home), plain(End)]
+        );
+    }
+
+    #[test]
+    fn editing_keys() {
+        use self::KeyCode::*;
+        assert_eq!(
+            keys(b"\x1B[2~\x1B[3~\x1B[5~\x1B[6~\x1B[Z"),
+            vec![
+                plain(Insert),
+                plain(Delete),
+                plain(PageUp),
+                plain(PageDown),
+                KeyEvent::new(Tab, KeyModifiers::SHIFT),
+            ]
+        );
+    }
+
+    #[test]
+    fn function_keys() {
+        let expected: Vec<_> = (1..=12).map(|n| plain(KeyCode::F(n))).collect();
+        assert_eq!(
+            keys(b"\x1BOP\x1BOQ\x1BOR\x1BOS\x1B[15~\x1B[17~\x1B[18~\x1B[19~\x1B[20~\x1B[21~\x1B[23~\x1B[24~"),
+            expected
+        );
+        assert_eq!(
+            keys(b"\x1B[11~\x1B[12~\x1B[13~\x1B[14~"),
+            expected[..4].to_vec()
+        );
+        // The Linux console
+        assert_eq!(
+            keys(b"\x1B[[A\x1B[[B\x1B[[C\x1B[[D\x1B[[E"),
+            expected[..5].to_vec()
+        );
+    }
+
+    #[test]
+    fn modifier_parameters() {
+        use self::KeyCode::*;
+        let ctrl = KeyModifiers::CONTROL;
+        let ctrl_shift = KeyModifiers {
+            shift: true,
+            control: true,
+            ..KeyModifiers::default()
+        };
+        assert_eq!(
+            keys(b"\x1B[1;5A\x1B[1;3D\x1B[3;6~\x1B[1;5P"),
+            vec![
+                KeyEvent::new(Up, ctrl),
+                alt(Left),
+                KeyEvent::new(Delete, ctrl_shift),
+                KeyEvent::new(F(1), ctrl),
+            ]
+        );
+    }
+
+    #[test]
+    fn control_characters() {
+        use self::KeyCode::*;
+        assert_eq!(
+            keys(b"\r\t\x7F\x01\x00"),
+            vec![
+                plain(Enter),
+                plain(Tab),
+                plain(Backspace),
+                KeyEvent::new(Char('a'), KeyModifiers::CONTROL),
+                KeyEvent::new(Char(' '), KeyModifiers::CONTROL),
+            ]
+        );
+    }
+
+    #[test]
+    fn alt_keys() {
+        use self::KeyCode::*;
+        assert_eq!(keys(b"\x1Bx\x1B\x7F"), vec![alt(Char('x')), alt(Backspace)]);
+        assert_eq!(keys(b"\x1B\x1B[A"), vec![alt(Up)]);
+    }
+
+    #[test]
+    fn lone_escape_waits_until_forced() {
+        let mut decoder = EventDecoder::default();
+        decoder.feed(b"\x1B");
+        assert_eq!(decoder.next(false), None);
+        assert!(decoder.has_pending_input());
+        assert_eq!(
+            decoder.next(true),
+            Some(Decoded::Event(Event::Key(plain(KeyCode::Esc))))
+        );
+        assert!(!decoder.has_pending_input());
+    }
+
+    #[test]
+    fn alt_brackets_are_resolved_when_forced() {
+        use self::KeyCode::*;
+        assert_eq!(keys(b"\x1B["), vec![alt(Char('['))]);
+        assert_eq!(keys(b"\x1BO"), vec![alt(Char('O'))]);
+    }
+
+    #[test]
+    fn split_sequences() {
+        let mut decoder = EventDecoder::default();
+        for chunk in [&b"\x1B"[..], b"[", b"1;", b"5"] {
+            decoder.feed(chunk);
+            assert_eq!(decoder.next(false), None);
+        }
+        decoder.feed(b"Ax");
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Event(Event::Key(KeyEvent::new(
+                KeyCode::Up,
+                KeyModifiers::CONTROL
+            ))))
+        );
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Event(Event::Key(plain(KeyCode::Char('x')))))
+        );
+        assert_eq!(decoder.next(false), None);
+    }
+
+    #[test]
+    fn cut_off_sequences_are_dropped() {
+        use self::KeyCode::*;
+        assert_eq!(
+            keys(b"\x1B[1;\x01a"),
+            vec![
+                KeyEvent::new(Char('a'), KeyModifiers::CONTROL),
+                plain(Char('a'))
+            ]
+        );
+        assert_eq!(keys(b"\x1B[99~z"), vec![plain(Char('z'))]);
+    }
+
+    #[test]
+    fn multibyte_utf8() {
+        use self::KeyCode::*;
+        assert_eq!(
+            keys("é€😀".as_bytes()),
+            vec![plain(Char('é')), plain(Char('€')), plain(Char('😀'))]
+        );
+
+        let mut decoder = EventDecoder::default();
+        decoder.feed(&"€".as_bytes()[..2]);
+        assert_eq!(decoder.next(false), None);
+        decoder.feed(&"€".as_bytes()[2..]);
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Event(Event::Key(plain(Char('€')))))
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_skipped() {
+        assert_eq!(keys(b"\xFFa\xC3"), vec![plain(KeyCode::Char('a'))]);
+    }
+
+    fn mouse(
+        kind: MouseEventKind,
+        button: Option<MouseButton>,
+        column: u16,
+        row: u16,
+    ) -> MouseEvent {
+        MouseEvent {
+            kind,
+            button,
+            column,
+            row,
+            modifiers: KeyModifiers::default(),
+        }
+    }
+
+    fn mouse_events(input: &[u8]) -> Vec<MouseEvent> {
+        decode(input)
+            .into_iter()
+            .map(|decoded| match decoded {
+                Decoded::Event(Event::Mouse(m)) => m,
+                d => panic!("expected a mouse event, got {:?}", d),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn default_mouse_encoding() {
+        use {MouseButton::*, MouseEventKind::*};
+        assert_eq!(
+            mouse_events(b"\x1B[M *+\x1B[M#*+\x1B[MB*+\x1B[M`!!"),
+            vec![
+                mouse(Press, Some(Left), 10, 11),
+                mouse(Release, None, 10, 11),
+                mouse(Drag, Some(Right), 10, 11),
+                mouse(ScrollUp, None, 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn urxvt_mouse_encoding() {
+        use {MouseButton::*, MouseEventKind::*};
+        assert_eq!(
+            mouse_events(b"\x1B[33;300;2M\x1B[67;5;6M"),
+            vec![mouse(Press, Some(Middle), 300, 2), mouse(Moved, None, 5, 6)]
+        );
+    }
+
+    #[test]
+    fn sgr_mouse_encoding() {
+        use {MouseButton::*, MouseEventKind::*};
+        let mut with_control = mouse(Press, Some(Left), 1000, 20);
+        with_control.modifiers = KeyModifiers::CONTROL;
+        assert_eq!(
+            mouse_events(
+                b"\x1B[<0;1000;20M\x1B[<0;1000;20m\x1B[<16;1000;20M\x1B[<65;3;4M\x1B[<66;3;4M"
+            ),
+            vec![
+                mouse(Press, Some(Left), 1000, 20),
+                mouse(Release, Some(Left), 1000, 20),
+                with_control,
+                mouse(ScrollDown, None, 3, 4),
+                mouse(ScrollLeft, None, 3, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn unnamed_mouse_buttons_are_dropped() {
+        assert_eq!(decode(b"\x1B[<128;1;1M"), vec![]);
+    }
+
+    #[test]
+    fn split_mouse_report() {
+        let mut decoder = EventDecoder::default();
+        decoder.feed(b"\x1B[M ");
+        assert_eq!(decoder.next(false), None);
+        decoder.feed(b"!!");
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Event(Event::Mouse(mouse(
+                MouseEventKind::Press,
+                Some(MouseButton::Left),
+                1,
+                1
+            ))))
+        );
+    }
+
+    #[test]
+    fn bracketed_paste() {
+        assert_eq!(
+            decode(b"\x1B[200~one\ntwo \x1B[A\x1B[201~x"),
+            vec![
+                Decoded::Event(Event::Paste("one\ntwo \x1B[A".to_string())),
+                Decoded::Event(Event::Key(plain(KeyCode::Char('x')))),
+            ]
+        );
+        assert_eq!(
+            decode(b"\x1B[200~\x1B[201~"),
+            vec![Decoded::Event(Event::Paste(String::new()))]
+        );
+    }
+
+    #[test]
+    fn split_paste() {
+        let mut decoder = EventDecoder::default();
+        decoder.feed(b"\x1B[200~caf\xC3");
+        assert_eq!(decoder.next(true), None);
+        decoder.feed(b"\xA9\x1B[20");
+        assert_eq!(decoder.next(true), None);
+        decoder.feed(b"1~");
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Event(Event::Paste("café".to_string())))
+        );
+    }
+
+    #[test]
+    fn focus_reports() {
+        assert_eq!(
+            decode(b"\x1B[I\x1B[O\x1BO"),
+            vec![
+                Decoded::Event(Event::FocusGained),
+                Decoded::Event(Event::FocusLost),
+                Decoded::Event(Event::Key(alt(KeyCode::Char('O')))),
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor_position_reports() {
+        let mut decoder = EventDecoder::default();
+        decoder.feed(b"\x1B[12;40R\x1B[1;5R");
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Reply(Reply::CursorPosition {
+                row: 12,
+                column: 40
+            }))
+        );
+        // Without a query in flight, this is Ctrl+F3.
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Event(Event::Key(KeyEvent::new(
+                KeyCode::F(3),
+                KeyModifiers::CONTROL
+            ))))
+        );
+
+        decoder.awaiting_cursor_position = true;
+        decoder.feed(b"\x1B[1;5R");
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Reply(Reply::CursorPosition { row: 1, column: 5 }))
+        );
+    }
+
+    #[test]
+    fn text_area_size_reports() {
+        assert_eq!(
+            decode(b"\x1B[8;24;80t\x1B[4;600;800t"),
+            vec![Decoded::Reply(Reply::TextAreaSize {
+                rows: 24,
+                columns: 80
+            })]
+        );
+    }
+
+    #[test]
+    fn device_attribute_reports() {
+        assert_eq!(
+            decode(b"\x1B[?65;1;9;22c\x1B[?1;2c\x1B[>41;390;0c\x1B[>1c"),
+            vec![
+                Decoded::Reply(Reply::PrimaryDeviceAttributes(PrimaryDeviceAttributes {
+                    conformance_level: 65,
+                    attributes: vec![1, 9, 22],
+                })),
+                Decoded::Reply(Reply::PrimaryDeviceAttributes(PrimaryDeviceAttributes {
+                    conformance_level: 1,
+                    attributes: vec![2],
+                })),
+                Decoded::Reply(Reply::SecondaryDeviceAttributes(
+                    SecondaryDeviceAttributes {
+                        terminal_type: 41,
+                        version: 390,
+                    }
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn terminal_version_reports() {
+        let version = |v: &str| Decoded::Reply(Reply::TerminalVersion(v.to_string()));
+        assert_eq!(
+            decode(b"\x1BP>|XTerm(390)\x1B\\\x1BP>|kitty(0.35.2)\x07\x1BP1$r0m\x1B\\"),
+            vec![version("XTerm(390)"), version("kitty(0.35.2)")]
+        );
+    }
+
+    #[test]
+    fn split_terminal_version_report() {
+        let mut decoder = EventDecoder::default();
+        decoder.feed(b"\x1BP>|foot\x1B");
+        assert_eq!(decoder.next(false), None);
+        decoder.feed(b"\\");
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Reply(Reply::TerminalVersion("foot".to_string())))
+        );
+    }
+
+    #[test]
+    fn private_mode_reports() {
+        let report = |mode, state| Decoded::Reply(Reply::PrivateMode { mode, state });
+        assert_eq!(
+            decode(b"\x1B[?2026;2$y\x1B[?2004;1$y\x1B[?1;4$y\x1B[?9999;0$y\x1B[?25;3y"),
+            vec![
+                report(2026, ModeState::Reset),
+                report(2004, ModeState::Set),
+                report(1, ModeState::PermanentlyReset),
+                report(9999, ModeState::NotRecognized),
+            ]
+        );
+    }
+
+    #[test]
+    fn alt_shift_p_is_resolved_when_forced() {
+        let mut decoder = EventDecoder::default();
+        decoder.feed(b"\x1BP");
+        assert_eq!(decoder.next(false), None);
+        assert_eq!(
+            decoder.next(true),
+            Some(Decoded::Event(Event::Key(alt(KeyCode::Char('P')))))
+        );
+    }
+
+    #[test]
+    fn clipboard_reports() {
+        assert_eq!(
+            decode(b"\x1B]52;c;aGVsbG8gw6k=\x07\x1B]52;p;\x1B\\\x1B]52;c;!!\x07"),
+            vec![
+                Decoded::Reply(Reply::Clipboard("hello é".to_string())),
+                Decoded::Reply(Reply::Clipboard(String::new())),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_clipboard_report() {
+        let mut decoder = EventDecoder::default();
+        decoder.feed(b"\x1B]52;c;aGk");
+        assert_eq!(decoder.next(true), None);
+        decoder.feed(b"=\x07");
+        assert_eq!(
+            decoder.next(false),
+            Some(Decoded::Reply(Reply::Clipboard("hi".to_string())))
+        );
+    }
+}
@@ -0,0 +1,72 @@
+/// Represents a single key press decoded from terminal input.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyEvent {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        KeyEvent { code, modifiers }
+    }
+}
+
+impl From<KeyCode> for KeyEvent {
+    fn from(code: KeyCode) -> Self {
+        KeyEvent::new(code, KeyModifiers::default())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum KeyCode {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Esc,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    /// A function key, numbered from 1.
+    F(u8),
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct KeyModifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub control: bool,
+}
+
+impl KeyModifiers {
+    pub(crate) const SHIFT: KeyModifiers = KeyModifiers {
+        shift: true,
+        alt: false,
+        control: false,
+    };
+
+    pub(crate) const CONTROL: KeyModifiers = KeyModifiers {
+        shift: false,
+        alt: false,
+        control: true,
+    };
+
+    /// Decodes the modifier parameter that xterm-style terminals append to CSI key sequences
+    /// (i.e., the `5` in `ESC[1;5A` for Ctrl+Up).
+    pub(crate) fn from_xterm_parameter(parameter: u16) -> Self {
+        let bits = parameter.saturating_sub(1);
+        KeyModifiers {
+            shift: bits & 0b0001 != 0,
+            // Meta is reported separately by some terminals, but nobody can tell it apart from Alt.
+            alt: bits & 0b1010 != 0,
+            control: bits & 0b0100 != 0,
+        }
+    }
+}
@@ -0,0 +1,530 @@
+mod decoder;
+pub mod keyboard;
+pub mod mouse;
+
+use {
+    self::{decoder::EventDecoder, keyboard::KeyEvent, mouse::MouseEvent},
+    crate::{
+        capabilities::{ModeState, PrimaryDeviceAttributes, SecondaryDeviceAttributes},
+        escapes::{
+            cursor::CursorEscape,
+            osc::{ClipboardTarget, OscEscape},
+            AnsiEscape,
+        },
+        AnsiTerminal, TerminalOutput,
+    },
+    std::{
+        collections::VecDeque,
+        io,
+        time::{Duration, Instant},
+    },
+};
+
+/// How long an `AnsiTerminal` waits by default for the rest of an escape sequence before deciding
+/// that a lone ESC byte was the Escape key being pressed.
+pub const DEFAULT_ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// How long an `AnsiTerminal` waits by default for the terminal to answer a query, like the one
+/// `AnsiTerminal::size` may need to make.
+pub const DEFAULT_REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Represents a single item of input decoded from the terminal.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    /// Text pasted while bracketed paste was enabled with `AnsiEscape::EnableBracketedPaste`,
+    /// exactly as the terminal sent it.
+    Paste(String),
+    /// Reported after enabling `AnsiEscape::EnableFocusReporting`.
+    FocusGained,
+    /// Reported after enabling `AnsiEscape::EnableFocusReporting`.
+    FocusLost,
+    /// The terminal window was resized to the given dimensions. Currently only reported on Unix,
+    /// where it follows `SIGWINCH`.
+    Resize {
+        columns: u16,
+        rows: u16,
+    },
+    /// The process was continued after being suspended, and the screen needs to be drawn again.
+    /// Reported on Unix once `UnixAnsiTerminal::enable_job_control` has been called.
+    Redraw,
+}
+
+/// Represents a terminal's answer to a query. Replies are handed to whoever sent the query instead
+/// of being delivered as `Event`s.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Reply {
+    /// `ESC[row;columnR`, in response to `CursorEscape::QueryPosition`
+    CursorPosition { row: u16, column: u16 },
+    /// `ESC[8;rows;columnst`, in response to `AnsiEscape::QueryTextAreaSize`
+    TextAreaSize { rows: u16, columns: u16 },
+    /// `ESC]52;target;contentBEL`, in response to `OscEscape::QueryClipboard`, with the content
+    /// already decoded
+    Clipboard(String),
+    /// `ESC[?level;attributes...c`, in response to `AnsiEscape::QueryPrimaryDeviceAttributes`
+    PrimaryDeviceAttributes(PrimaryDeviceAttributes),
+    /// `ESC[>type;version;0c`, in response to `AnsiEscape::QuerySecondaryDeviceAttributes`
+    SecondaryDeviceAttributes(SecondaryDeviceAttributes),
+    /// `ESCP>|versionESC\`, in response to `AnsiEscape::QueryTerminalVersion`
+    TerminalVersion(String),
+    /// `ESC[?mode;state$y`, in response to `AnsiEscape::QueryPrivateMode`
+    PrivateMode { mode: u16, state: ModeState },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Decoded {
+    Event(Event),
+    Reply(Reply),
+}
+
+/// Represents what an `EventSource` received while waiting for input.
+pub(crate) enum Received {
+    /// This many bytes of input were read. `0` means the input stream has ended.
+    Bytes(usize),
+    /// Something besides the input stream produced an event, like a signal.
+    Event(Event),
+    /// Nothing arrived in time.
+    Nothing,
+}
+
+/// A source of raw bytes from a terminal's input stream.
+pub(crate) trait EventSource {
+    /// Reads whatever input is available into `buf`, waiting at most `timeout` (or indefinitely
+    /// if `None`) for some to arrive.
+    fn receive(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<Received>;
+}
+
+/// The platform-independent state that an `AnsiTerminal` keeps for decoding its input.
+#[derive(Debug)]
+pub(crate) struct InputState {
+    decoder: EventDecoder,
+    /// Events that arrived while waiting for the reply to a query
+    pending: VecDeque<Event>,
+    /// When the input that the decoder is holding on to first couldn't be decoded
+    undecoded_since: Option<Instant>,
+    escape_timeout: Duration,
+    reply_timeout: Duration,
+}
+
+impl InputState {
+    pub(crate) fn new() -> Self {
+        InputState {
+            decoder: EventDecoder::default(),
+            pending: VecDeque::new(),
+            undecoded_since: None,
+            escape_timeout: DEFAULT_ESCAPE_TIMEOUT,
+            reply_timeout: DEFAULT_REPLY_TIMEOUT,
+        }
+    }
+
+    pub(crate) fn set_escape_timeout(&mut self, timeout: Duration) {
+        self.escape_timeout = timeout;
+    }
+
+    pub(crate) fn set_reply_timeout(&mut self, timeout: Duration) {
+        self.reply_timeout = timeout;
+    }
+
+    /// Reads from `source` until an event can be decoded or `timeout` elapses. A `timeout` of
+    /// `None` waits indefinitely.
+    pub(crate) fn poll_event<S: EventSource>(
+        &mut self,
+        source: &mut S,
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<Event>> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(Some(event));
+        }
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            match self.poll_decoded(source, deadline)? {
+                Some(Decoded::Event(event)) => return Ok(Some(event)),
+                // Nobody is waiting for this anymore, so its query must have timed out.
+                Some(Decoded::Reply(_)) => (),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Waits for the terminal to answer a query that was just sent, keeping any events that
+    /// arrive in the meantime for `poll_event`. Returns `None` if no reply that `accept` turns
+    /// into a value arrives before the reply timeout.
+    pub(crate) fn wait_for_reply<S, T, F>(
+        &mut self,
+        source: &mut S,
+        mut accept: F,
+    ) -> io::Result<Option<T>>
+    where
+        S: EventSource,
+        F: FnMut(Reply) -> Option<T>,
+    {
+        let deadline = Instant::now() + self.reply_timeout;
+        loop {
+            match self.poll_decoded(source, Some(deadline))? {
+                Some(Decoded::Event(event)) => self.pending.push_back(event),
+                Some(Decoded::Reply(reply)) => {
+                    if let Some(t) = accept(reply) {
+                        return Ok(Some(t));
+                    }
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Like `wait_for_reply`, for a query that was sent followed by
+    /// `AnsiEscape::QueryPrimaryDeviceAttributes`. Terminals answer queries in order and all of
+    /// them answer that one, so its reply arriving first means that the query was ignored, which
+    /// gives `Some(None)` without waiting for the reply timeout. Returns `None` if neither reply
+    /// arrives in time.
+    pub(crate) fn wait_for_reply_before_sentinel<S, T, F>(
+        &mut self,
+        source: &mut S,
+        mut accept: F,
+    ) -> io::Result<Option<Option<T>>>
+    where
+        S: EventSource,
+        F: FnMut(Reply) -> Option<T>,
+    {
+        let answer = self.wait_for_reply(source, |reply| match reply {
+            Reply::PrimaryDeviceAttributes(_) => Some(None),
+            reply => accept(reply).map(Some),
+        })?;
+        if let Some(Some(_)) = answer {
+            // The sentinel's reply is still on its way, and mustn't be taken for the answer to a
+            // later query.
+            self.wait_for_reply(source, |reply| match reply {
+                Reply::PrimaryDeviceAttributes(_) => Some(()),
+                _ => None,
+            })?;
+        }
+        Ok(answer)
+    }
+
+    /// Waits for the reply to `CursorEscape::QueryPosition`, as `(row, column)`.
+    pub(crate) fn wait_for_cursor_position<S: EventSource>(
+        &mut self,
+        source: &mut S,
+    ) -> io::Result<Option<(u16, u16)>> {
+        self.decoder.awaiting_cursor_position = true;
+        let position = self.wait_for_reply(source, |reply| match reply {
+            Reply::CursorPosition { row, column } => Some((row, column)),
+            _ => None,
+        });
+        self.decoder.awaiting_cursor_position = false;
+        position
+    }
+
+    fn poll_decoded<S: EventSource>(
+        &mut self,
+        source: &mut S,
+        deadline: Option<Instant>,
+    ) -> io::Result<Option<Decoded>> {
+        let mut buf = [0; 8192];
+        let mut timed_out = false;
+        loop {
+            if let Some(decoded) = self.decoder.next(false) {
+                self.undecoded_since = None;
+                return Ok(Some(decoded));
+            }
+
+            // Once part of an escape sequence has arrived, the rest of it should follow right
+            // away. If it doesn't, the bytes we have must have been typed on their own. This is
+            // timed from when they arrived, since callers may poll for less than the escape
+            // timeout at a time.
+            let now = Instant::now();
+            let mut escape_deadline = match self.decoder.has_pending_input() {
+                true => Some(*self.undecoded_since.get_or_insert(now) + self.escape_timeout),
+                false => {
+                    self.undecoded_since = None;
+                    None
+                }
+            };
+            if escape_deadline.is_some_and(|d| now >= d) {
+                if let Some(decoded) = self.decoder.next(true) {
+                    self.undecoded_since = None;
+                    return Ok(Some(decoded));
+                }
+                // Pastes and clipboard contents can take as long as they like.
+                escape_deadline = None;
+            }
+            if timed_out {
+                return Ok(None);
+            }
+
+            let wait = match (deadline, escape_deadline) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            match source.receive(&mut buf, wait.map(|d| d.saturating_duration_since(now)))? {
+                Received::Bytes(0) => {
+                    return match self.decoder.next(true) {
+                        Some(decoded) => Ok(Some(decoded)),
+                        None => Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "terminal input stream closed",
+                        )),
+                    };
+                }
+                Received::Bytes(n) => {
+                    // Sources that can't time out may only return long after the escape timeout,
+                    // which settles what came before.
+                    let late = escape_deadline.is_some_and(|d| Instant::now() >= d);
+                    let settled = match late {
+                        true => self.decoder.next(true),
+                        false => None,
+                    };
+                    self.decoder.feed(&buf[..n]);
+                    if settled.is_some() {
+                        self.undecoded_since = None;
+                        return Ok(settled);
+                    }
+                }
+                Received::Event(event) => return Ok(Some(Decoded::Event(event))),
+                Received::Nothing => timed_out = deadline.is_some_and(|d| Instant::now() >= d),
+            }
+        }
+    }
+}
+
+/// What differs between platforms when an `AnsiTerminal` asks the terminal something, so that
+/// the queries themselves can be shared.
+pub(crate) trait RawInput: AnsiTerminal + Sized {
+    type Source<'a>: EventSource
+    where
+        Self: 'a;
+
+    /// Splits off what's needed to read input, so that both halves can be borrowed at once.
+    fn split_input(&mut self) -> (&mut InputState, Self::Source<'_>);
+
+    /// Runs `f` with the input stream set up so that the terminal's replies can be read as soon
+    /// as they arrive instead of being echoed back to it, and puts it back afterwards, even if
+    /// `f` fails.
+    fn with_raw_input<T, F>(&mut self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Self) -> io::Result<T>;
+}
+
+/// Sends `query` and waits for the reply that `accept` turns into a value, failing with
+/// `io::ErrorKind::TimedOut` if it doesn't arrive. `what` says what the reply would have reported.
+fn query<R, Q, T, F>(terminal: &mut R, query: &Q, accept: F, what: &str) -> io::Result<T>
+where
+    R: RawInput,
+    Q: TerminalOutput,
+    F: FnMut(Reply) -> Option<T>,
+{
+    terminal.with_raw_input(|t| {
+        t.write(query)?;
+        t.flush()?;
+        let (input, mut source) = t.split_input();
+        input.wait_for_reply(&mut source, accept)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("terminal did not report {}", what),
+            )
+        })
+    })
+}
+
+/// Sends `query` followed by `AnsiEscape::QueryPrimaryDeviceAttributes` and waits for the reply
+/// that `accept` turns into a value. See `InputState::wait_for_reply_before_sentinel`.
+fn query_before_sentinel<R, T, F>(
+    terminal: &mut R,
+    query: &AnsiEscape,
+    accept: F,
+) -> io::Result<Option<T>>
+where
+    R: RawInput,
+    F: FnMut(Reply) -> Option<T>,
+{
+    terminal.with_raw_input(|t| {
+        t.write(query)?;
+        t.write(&AnsiEscape::QueryPrimaryDeviceAttributes)?;
+        t.flush()?;
+        let (input, mut source) = t.split_input();
+        input
+            .wait_for_reply_before_sentinel(&mut source, accept)?
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::TimedOut, "terminal did not answer the query")
+            })
+    })
+}
+
+/// Implements `AnsiTerminal::query_cursor_position`.
+pub(crate) fn query_cursor_position<R: RawInput>(terminal: &mut R) -> io::Result<(u16, u16)> {
+    terminal.with_raw_input(|t| {
+        t.write(&CursorEscape::QueryPosition)?;
+        t.flush()?;
+        let (input, mut source) = t.split_input();
+        input.wait_for_cursor_position(&mut source)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                "terminal did not report the cursor position",
+            )
+        })
+    })
+}
+
+/// Implements `AnsiTerminal::query_clipboard`.
+pub(crate) fn query_clipboard<R: RawInput>(
+    terminal: &mut R,
+    target: ClipboardTarget,
+) -> io::Result<String> {
+    query(
+        terminal,
+        &OscEscape::QueryClipboard(target),
+        |reply| match reply {
+            Reply::Clipboard(content) => Some(content),
+            _ => None,
+        },
+        "the clipboard contents",
+    )
+}
+
+/// Implements `AnsiTerminal::query_primary_device_attributes`.
+pub(crate) fn query_primary_device_attributes<R: RawInput>(
+    terminal: &mut R,
+) -> io::Result<PrimaryDeviceAttributes> {
+    query(
+        terminal,
+        &AnsiEscape::QueryPrimaryDeviceAttributes,
+        |reply| match reply {
+            Reply::PrimaryDeviceAttributes(attributes) => Some(attributes),
+            _ => None,
+        },
+        "its device attributes",
+    )
+}
+
+/// Implements `AnsiTerminal::query_secondary_device_attributes`.
+pub(crate) fn query_secondary_device_attributes<R: RawInput>(
+    terminal: &mut R,
+) -> io::Result<Option<SecondaryDeviceAttributes>> {
+    query_before_sentinel(
+        terminal,
+        &AnsiEscape::QuerySecondaryDeviceAttributes,
+        |reply| match reply {
+            Reply::SecondaryDeviceAttributes(attributes) => Some(attributes),
+            _ => None,
+        },
+    )
+}
+
+/// Implements `AnsiTerminal::query_terminal_version`.
+pub(crate) fn query_terminal_version<R: RawInput>(terminal: &mut R) -> io::Result<Option<String>> {
+    query_before_sentinel(
+        terminal,
+        &AnsiEscape::QueryTerminalVersion,
+        |reply| match reply {
+            Reply::TerminalVersion(version) => Some(version),
+            _ => None,
+        },
+    )
+}
+
+/// Implements `AnsiTerminal::query_private_mode`.
+pub(crate) fn query_private_mode<R: RawInput>(
+    terminal: &mut R,
+    mode: u16,
+) -> io::Result<ModeState> {
+    let state =
+        query_before_sentinel(
+            terminal,
+            &AnsiEscape::QueryPrivateMode(mode),
+            |reply| match reply {
+                Reply::PrivateMode { mode: m, state } if m == mode => Some(state),
+                _ => None,
+            },
+        )?;
+    Ok(state.unwrap_or(ModeState::NotRecognized))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::input::keyboard::{KeyCode, KeyModifiers},
+        std::thread,
+    };
+
+    /// Hands out `chunks` one read at a time, where `None` waits out the timeout without any
+    /// input, and then keeps waiting out every timeout.
+    struct ScriptedSource {
+        chunks: VecDeque<Option<&'static [u8]>>,
+    }
+
+    impl EventSource for ScriptedSource {
+        fn receive(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<Received> {
+            match self.chunks.pop_front().unwrap_or_default() {
+                Some(chunk) => {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                    Ok(Received::Bytes(chunk.len()))
+                }
+                None => {
+                    thread::sleep(timeout.expect("waited for input without a timeout"));
+                    Ok(Received::Nothing)
+                }
+            }
+        }
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn lone_escape_is_resolved_across_short_polls() {
+        let mut input = InputState::new();
+        input.set_escape_timeout(Duration::from_millis(30));
+        let mut source = ScriptedSource {
+            chunks: vec![Some(&b"\x1B"[..])].into(),
+        };
+        let mut events = Vec::new();
+        for _ in 0..20 {
+            if let Some(event) = input
+                .poll_event(&mut source, Some(Duration::from_millis(5)))
+                .unwrap()
+            {
+                events.push(event);
+            }
+        }
+        assert_eq!(events, [key(KeyCode::Esc, KeyModifiers::default())]);
+    }
+
+    #[test]
+    fn lone_escape_is_resolved_by_zero_timeout_polls() {
+        let mut input = InputState::new();
+        input.set_escape_timeout(Duration::from_millis(10));
+        let mut source = ScriptedSource {
+            chunks: vec![Some(&b"\x1B"[..])].into(),
+        };
+        assert_eq!(
+            input
+                .poll_event(&mut source, Some(Duration::from_secs(0)))
+                .unwrap(),
+            None
+        );
+        thread::sleep(Duration::from_millis(15));
+        assert_eq!(
+            input
+                .poll_event(&mut source, Some(Duration::from_secs(0)))
+                .unwrap(),
+            Some(key(KeyCode::Esc, KeyModifiers::default()))
+        );
+    }
+
+    #[test]
+    fn escape_sequences_are_not_split_by_short_polls() {
+        let mut input = InputState::new();
+        let mut source = ScriptedSource {
+            chunks: vec![Some(&b"\x1B"[..]), None, Some(b"[A")].into(),
+        };
+        let timeout = Some(Duration::from_millis(1));
+        assert_eq!(input.poll_event(&mut source, timeout).unwrap(), None);
+        assert_eq!(
+            input.poll_event(&mut source, timeout).unwrap(),
+            Some(key(KeyCode::Up, KeyModifiers::default()))
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use super::keyboard::KeyModifiers;
+
+/// Represents a mouse report decoded from terminal input. Reports are only sent after enabling
+/// them with `AnsiEscape::EnableMouseTracking`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct MouseEvent {
+    pub kind: MouseEventKind,
+    /// The button involved, if the terminal reported one. Releases in the default and urxvt
+    /// encodings, motion with no buttons held and scrolling have none.
+    pub button: Option<MouseButton>,
+    /// 1-based, like the coordinates of `CursorEscape::Position`.
+    pub column: u16,
+    /// 1-based, like the coordinates of `CursorEscape::Position`.
+    pub row: u16,
+    pub modifiers: KeyModifiers,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MouseEventKind {
+    Press,
+    Release,
+    Drag,
+    Moved,
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl MouseEvent {
+    /// Decodes the button code that all mouse encodings share, with the offset of 32 used by
+    /// the default and urxvt encodings already removed. `released` is only known for SGR
+    /// reports, which mark releases with a different final byte.
+    pub(crate) fn from_report(code: u16, column: u16, row: u16, released: bool) -> Option<Self> {
+        use self::{MouseButton::*, MouseEventKind::*};
+
+        let modifiers = KeyModifiers {
+            shift: code & 4 != 0,
+            alt: code & 8 != 0,
+            control: code & 16 != 0,
+        };
+        let motion = code & 32 != 0;
+        let low_bits = code & 0b11;
+
+        let (kind, button) = if code & 128 != 0 {
+            // Buttons 8 through 11, which we don't have names for.
+            return None;
+        } else if code & 64 != 0 {
+            let kind = match low_bits {
+                0 => ScrollUp,
+                1 => ScrollDown,
+                2 => ScrollLeft,
+                _ => ScrollRight,
+            };
+            (kind, None)
+        } else {
+            let button = match low_bits {
+                0 => Some(Left),
+                1 => Some(Middle),
+                2 => Some(Right),
+                _ => None,
+            };
+            let kind = match (released, motion, button) {
+                (true, _, _) => Release,
+                (false, true, Some(_)) => Drag,
+                (false, true, None) => Moved,
+                (false, false, Some(_)) => Press,
+                (false, false, None) => Release,
+            };
+            (kind, button)
+        };
+
+        Some(MouseEvent {
+            kind,
+            button,
+            column,
+            row,
+            modifiers,
+        })
+    }
+}
@@ -1,18 +1,31 @@
 #![deny(warnings)]
 
 use {
+    crate::{
+        capabilities::{
+            Capabilities, ColorDepth, ModeState, PrimaryDeviceAttributes, SecondaryDeviceAttributes,
+        },
+        escapes::osc::ClipboardTarget,
+        input::Event,
+    },
     failure::Fail,
     std::{
-        fmt, io,
-        io::{stdout, Write},
+        fmt,
+        io::{self, Read, Write},
+        time::Duration,
     },
 };
 
 #[macro_use]
 mod macros;
 
+pub mod capabilities;
 pub mod escapes;
+pub mod input;
+mod output;
 pub mod prelude;
+#[cfg(feature = "terminfo")]
+pub mod terminfo;
 
 #[cfg(windows)]
 pub mod windows;
@@ -29,13 +42,89 @@ use self::unix::UnixAnsiTerminal;
 pub trait AnsiTerminal {
     fn set_mode(&mut self, options: TerminalModeOptions) -> Result<(), TerminalModeSetError>;
 
-    fn write<T: TerminalOutput>(&mut self, t: &T) -> io::Result<()> {
-        t.fmt(&mut stdout())
-    }
+    fn write<T: TerminalOutput>(&mut self, t: &T) -> io::Result<()>;
 
-    fn flush(&mut self) -> io::Result<()> {
-        stdout().flush()
-    }
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Makes everything written from now on fit in `depth` colors. Extended colors in SGR escapes
+    /// are swapped for the closest ones available, and colors are left out entirely for
+    /// `ColorDepth::Monochrome`. This also applies to escapes embedded in text, like from
+    /// another program's output. Defaults to `ColorDepth::TrueColor`, which writes everything as
+    /// is; `capabilities` can tell what the terminal supports.
+    fn set_color_depth(&mut self, depth: ColorDepth);
+
+    /// Runs `f`, keeping everything it writes until it's done and then writing it all at once as
+    /// a synchronized update, so that the terminal never shows a frame half-drawn. Since nothing
+    /// reaches the terminal until `f` returns, queries that wait for the terminal's answer can't
+    /// be made in it. Frames within frames become part of the outermost one.
+    fn frame<T, F>(&mut self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Self) -> io::Result<T>;
+
+    /// Blocks until the next input event can be read from the terminal. Input is only delivered
+    /// key by key when the input stream is in raw mode; see `TerminalModeOptions::raw`.
+    fn read_event(&mut self) -> io::Result<Event>;
+
+    /// Like `read_event`, but gives up and returns `None` if no event arrives within `timeout`.
+    /// Terminals driven through streams that can't time out, like those from
+    /// `ansi_terminal_with_streams`, wait for input regardless.
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>>;
+
+    /// Sets how long to wait for the rest of an escape sequence before deciding that a lone ESC
+    /// byte was the Escape key being pressed. Defaults to `input::DEFAULT_ESCAPE_TIMEOUT`. When
+    /// input is read through streams that can't time out, a lone ESC is only reported once more
+    /// input arrives after the timeout, or the stream ends.
+    fn set_escape_timeout(&mut self, timeout: Duration);
+
+    /// Sets how long to wait for the terminal to answer a query. Defaults to
+    /// `input::DEFAULT_REPLY_TIMEOUT`.
+    fn set_reply_timeout(&mut self, timeout: Duration);
+
+    /// Gets the size of the terminal as `(columns, rows)`. Where the operating system doesn't
+    /// know it, the terminal is asked directly, which needs the input stream to be a terminal.
+    /// Input events that arrive while waiting for its answer are kept for `read_event`.
+    fn size(&mut self) -> io::Result<(u16, u16)>;
+
+    /// Asks the terminal where the cursor is, as `(row, column)` like `CursorEscape::Position`
+    /// takes them. On Unix, the input stream is put into raw mode until the answer arrives, so
+    /// that it isn't echoed. Input events that arrive while waiting for it are kept for
+    /// `read_event`.
+    fn query_cursor_position(&mut self) -> io::Result<(u16, u16)>;
+
+    /// Asks the terminal what's on one of its clipboards, which many terminals don't allow. Like
+    /// `query_cursor_position`, input events that arrive while waiting for the answer are kept
+    /// for `read_event`.
+    fn query_clipboard(&mut self, target: ClipboardTarget) -> io::Result<String>;
+
+    /// Asks the terminal for its conformance level and features. Every terminal answers this,
+    /// which makes it a good way to tell whether there's a terminal on the other end at all.
+    fn query_primary_device_attributes(&mut self) -> io::Result<PrimaryDeviceAttributes>;
+
+    /// Asks the terminal for its type and version. Returns `None` if the terminal doesn't answer
+    /// this.
+    ///
+    /// Queries that not every terminal answers are followed by the one that
+    /// `query_primary_device_attributes` makes, so that they don't have to wait for the reply
+    /// timeout when the terminal ignores them. Like `query_cursor_position`, input events that
+    /// arrive while waiting for the answer are kept for `read_event`.
+    fn query_secondary_device_attributes(
+        &mut self,
+    ) -> io::Result<Option<SecondaryDeviceAttributes>>;
+
+    /// Asks the terminal for its name and version, like `XTerm(388)` or `WezTerm 20240203`.
+    /// Returns `None` if the terminal doesn't answer this, which is common. Works like
+    /// `query_secondary_device_attributes`.
+    fn query_terminal_version(&mut self) -> io::Result<Option<String>>;
+
+    /// Asks the terminal whether the private mode `mode` is set, like 2026 for
+    /// `AnsiEscape::BeginSynchronizedUpdate`. Terminals that don't answer don't know the mode.
+    /// Works like `query_secondary_device_attributes`.
+    fn query_private_mode(&mut self, mode: u16) -> io::Result<ModeState>;
+
+    /// Guesses what the terminal supports with `Capabilities::detect`, going by whether the
+    /// output stream is a terminal. Streams given to `ansi_terminal_with_streams` don't count as
+    /// terminals, so use `Capabilities::from_env` for those instead.
+    fn capabilities(&self) -> Capabilities;
 }
 
 /// Convenience wrapper around `ansi_terminal_with_config` that defaults to all channels set to
@@ -64,6 +153,23 @@ pub fn ansi_terminal_with_config(
     })
 }
 
+/// Constructs an AnsiTerminal instance that reads input from `reader` and writes output to
+/// `writer` instead of the standard streams, like a socket for a remote session or in-memory
+/// buffers for tests. Terminal modes can't be set through arbitrary streams, so `set_mode` does
+/// nothing for the instance returned. Reading from them can't time out either, so `poll_event`
+/// blocks like `read_event` does; on Unix, streams with file descriptors, like sockets, can be
+/// given to `UnixAnsiTerminal::from_tty_streams` instead.
+pub fn ansi_terminal_with_streams<I: Read, O: Write>(reader: I, writer: O) -> impl AnsiTerminal {
+    #[cfg(windows)]
+    {
+        WindowsAnsiTerminal::from_streams(reader, writer)
+    }
+    #[cfg(unix)]
+    {
+        UnixAnsiTerminal::from_streams(reader, writer)
+    }
+}
+
 /// Represents an error encountered while constructing an `AnsiTerminal`.
 #[derive(Debug, Fail)]
 pub enum TerminalSetupError {
@@ -73,6 +179,10 @@ pub enum TerminalSetupError {
     Stdout(io::Error),
     #[fail(display = "unable to set up initial terminal state: {}", _0)]
     CouldNotSetInitialTermState(TerminalModeSetError),
+    #[fail(display = "unable to install signal handler: {}", _0)]
+    SignalHandler(io::Error),
+    #[fail(display = "unable to open the controlling terminal: {}", _0)]
+    Tty(io::Error),
 }
 
 impl From<TerminalModeSetError> for TerminalSetupError {
@@ -82,33 +192,110 @@ impl From<TerminalModeSetError> for TerminalSetupError {
 }
 
 /// Represents a abstract, coarse-grained mode that one of the standard streams can be set to.
+#[derive(Clone, Copy, Debug)]
 pub enum TerminalChannelMode {
     Cooked,
     Raw,
 }
 
 /// Represents an abstract, coarse-grained set of modes for each standard stream that
-/// `AnsiTerminal` manipulates.
+/// `AnsiTerminal` manipulates. Individual settings can be overridden on top of those with the
+/// builder methods, like `TerminalModeOptions::raw().output_processing(true)`. Overrides apply to
+/// whichever streams are terminals.
+#[derive(Clone, Debug)]
 pub struct TerminalModeOptions {
     stdin: TerminalChannelMode,
     stdout: TerminalChannelMode,
+    echo: Option<bool>,
+    canonical: Option<bool>,
+    signals: Option<bool>,
+    output_processing: Option<bool>,
+    flow_control: Option<bool>,
+    read_minimum: Option<u8>,
+    read_timeout: Option<Duration>,
 }
 
 impl TerminalModeOptions {
-    pub fn cooked() -> Self {
-        use crate::TerminalChannelMode::*;
+    fn new(stdin: TerminalChannelMode, stdout: TerminalChannelMode) -> Self {
         TerminalModeOptions {
-            stdin: Cooked,
-            stdout: Cooked,
+            stdin,
+            stdout,
+            echo: None,
+            canonical: None,
+            signals: None,
+            output_processing: None,
+            flow_control: None,
+            read_minimum: None,
+            read_timeout: None,
         }
     }
 
+    pub fn cooked() -> Self {
+        use crate::TerminalChannelMode::*;
+        TerminalModeOptions::new(Cooked, Cooked)
+    }
+
     pub fn raw() -> Self {
         use crate::TerminalChannelMode::*;
-        TerminalModeOptions {
-            stdin: Raw,
-            stdout: Raw,
-        }
+        TerminalModeOptions::new(Raw, Raw)
+    }
+
+    /// Input is delivered key by key without being echoed, but everything else stays cooked, so
+    /// that Ctrl-C still interrupts the process and output still starts new lines at the left
+    /// edge. Reads wait for at least one byte, like they do in raw mode, instead of depending on
+    /// whatever `VMIN` and `VTIME` happen to be in the cooked settings.
+    pub fn cbreak() -> Self {
+        TerminalModeOptions::cooked()
+            .echo(false)
+            .canonical(false)
+            .read_minimum(1)
+            .read_timeout(Duration::from_secs(0))
+    }
+
+    /// Whether input is echoed back as it's typed.
+    pub fn echo(mut self, enabled: bool) -> Self {
+        self.echo = Some(enabled);
+        self
+    }
+
+    /// Whether input is gathered into lines that can be edited before they're delivered.
+    pub fn canonical(mut self, enabled: bool) -> Self {
+        self.canonical = Some(enabled);
+        self
+    }
+
+    /// Whether keys like Ctrl-C and Ctrl-Z send signals to the process instead of being delivered
+    /// as input.
+    pub fn signals(mut self, enabled: bool) -> Self {
+        self.signals = Some(enabled);
+        self
+    }
+
+    /// Whether output is post-processed, like translating `\n` into `\r\n`.
+    pub fn output_processing(mut self, enabled: bool) -> Self {
+        self.output_processing = Some(enabled);
+        self
+    }
+
+    /// Whether Ctrl-S and Ctrl-Q pause and resume output instead of being delivered as input.
+    /// Only supported on Unix.
+    pub fn flow_control(mut self, enabled: bool) -> Self {
+        self.flow_control = Some(enabled);
+        self
+    }
+
+    /// How many bytes of input a read waits for when input isn't canonical, like `VMIN`. Only
+    /// supported on Unix.
+    pub fn read_minimum(mut self, bytes: u8) -> Self {
+        self.read_minimum = Some(bytes);
+        self
+    }
+
+    /// How long a read waits for input when input isn't canonical, like `VTIME`. Rounded down to
+    /// tenths of a second, up to 25.5 seconds. Only supported on Unix.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
     }
 }
 
@@ -169,3 +356,143 @@ macro_rules! out {
         out!(@args $t; $($tail)*);
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        escapes::cursor::CursorEscape,
+        input::keyboard::{KeyCode, KeyEvent, KeyModifiers},
+        std::{collections::VecDeque, thread},
+    };
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn round_trip_through_in_memory_streams() {
+        let input = &b"a\x1B[1;5A\x1B[200~pasted\x1B[201~"[..];
+        let mut output = Vec::new();
+        {
+            let mut t = ansi_terminal_with_streams(input, &mut output);
+            assert_eq!(
+                t.read_event().unwrap(),
+                key(KeyCode::Char('a'), KeyModifiers::default())
+            );
+            assert_eq!(
+                t.poll_event(Duration::from_millis(10)).unwrap(),
+                Some(key(KeyCode::Up, KeyModifiers::CONTROL))
+            );
+            assert_eq!(t.read_event().unwrap(), Event::Paste("pasted".to_string()));
+            assert_eq!(
+                t.read_event().unwrap_err().kind(),
+                io::ErrorKind::UnexpectedEof
+            );
+
+            t.write(&CursorEscape::Position(2, 3)).unwrap();
+            t.write(&format_args!("text")).unwrap();
+            t.flush().unwrap();
+        }
+        assert_eq!(output, b"\x1B[2;3Htext\x1B[0m");
+    }
+
+    /// Waits `delay` before handing out each of `chunks`, like a socket that can't time out.
+    struct SlowReader {
+        chunks: VecDeque<&'static [u8]>,
+        delay: Duration,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(self.delay);
+            let chunk = self.chunks.pop_front().unwrap_or_default();
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn late_input_settles_a_lone_escape() {
+        let reader = SlowReader {
+            chunks: vec![&b"\x1B"[..], b"x"].into(),
+            delay: Duration::from_millis(30),
+        };
+        let mut t = ansi_terminal_with_streams(reader, io::sink());
+        t.set_escape_timeout(Duration::from_millis(10));
+        assert_eq!(
+            t.read_event().unwrap(),
+            key(KeyCode::Esc, KeyModifiers::default())
+        );
+        assert_eq!(
+            t.read_event().unwrap(),
+            key(KeyCode::Char('x'), KeyModifiers::default())
+        );
+    }
+
+    /// Keeps what's written, and what had been written each time it was flushed.
+    #[derive(Default)]
+    struct RecordingWriter {
+        written: Vec<u8>,
+        flushed: Vec<Vec<u8>>,
+    }
+
+    impl Write for RecordingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed.push(self.written.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn frames_are_synchronized_updates() {
+        let mut output = Vec::new();
+        {
+            let mut t = ansi_terminal_with_streams(&b""[..], &mut output);
+            t.frame(|t| t.write(&format_args!("drawn"))).unwrap();
+        }
+        assert_eq!(output, b"\x1B[?2026hdrawn\x1B[?2026l\x1B[0m");
+    }
+
+    #[test]
+    fn frames_are_written_at_once() {
+        let mut output = RecordingWriter::default();
+        {
+            let mut t = ansi_terminal_with_streams(&b""[..], &mut output);
+            t.write(&format_args!("before")).unwrap();
+            t.frame(|t| {
+                t.write(&format_args!("a"))?;
+                t.flush()?;
+                // Frames within frames are part of the outer one.
+                t.frame(|t| t.write(&format_args!("b")))?;
+                t.write(&format_args!("c"))
+            })
+            .unwrap();
+        }
+        assert_eq!(
+            output.flushed,
+            vec![
+                b"before\x1B[?2026habc\x1B[?2026l".to_vec(),
+                b"before\x1B[?2026habc\x1B[?2026l\x1B[0m".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_frames_are_still_written() {
+        let mut output = Vec::new();
+        {
+            let mut t = ansi_terminal_with_streams(&b""[..], &mut output);
+            let result: io::Result<()> = t.frame(|t| {
+                t.write(&format_args!("partial"))?;
+                Err(io::Error::other("failed"))
+            });
+            assert_eq!(result.unwrap_err().to_string(), "failed");
+        }
+        assert_eq!(output, b"\x1B[?2026hpartial\x1B[?2026l\x1B[0m");
+    }
+}
@@ -9,3 +9,9 @@ macro_rules! csi {
     // See here: https://en.wikipedia.org/wiki/ANSI_escape_code#CSI_sequences
     ($($l: expr),*) => { ansi!("[", $($l),*) };
 }
+
+#[macro_export]
+macro_rules! osc {
+    // See here: https://en.wikipedia.org/wiki/ANSI_escape_code#OSC_(Operating_System_Command)_sequences
+    ($($l: expr),*) => { ansi!("]", $($l),*) };
+}
@@ -0,0 +1,86 @@
+use {
+    crate::{
+        capabilities::ColorDepth,
+        escapes::{cursor::CursorStyleTracker, formatting::fmt_downsampled, AnsiEscape, Observe},
+        TerminalOutput,
+    },
+    std::io::{self, Write},
+};
+
+/// What `AnsiTerminal`s on every platform need to write output: the stream itself, what gets
+/// watched for in what's written to it, and the frame that output is held back for.
+#[derive(Debug)]
+pub(crate) struct Output<W, O> {
+    pub(crate) writer: W,
+    pub(crate) cursor_style: CursorStyleTracker,
+    /// Whatever else the platform watches the output for
+    pub(crate) observer: O,
+    pub(crate) color_depth: ColorDepth,
+    /// Output held back until the current `frame` is done
+    frame: Option<Vec<u8>>,
+}
+
+impl<W: Write, O: Observe> Output<W, O> {
+    pub(crate) fn new(writer: W, observer: O) -> Self {
+        Output {
+            writer,
+            cursor_style: CursorStyleTracker::default(),
+            observer,
+            color_depth: ColorDepth::TrueColor,
+            frame: None,
+        }
+    }
+
+    pub(crate) fn write<T: TerminalOutput>(&mut self, t: &T) -> io::Result<()> {
+        match &mut self.frame {
+            Some(frame) => fmt_downsampled(
+                t,
+                self.color_depth,
+                &mut self.observer.track(&mut self.cursor_style.track(frame)),
+            ),
+            None => fmt_downsampled(
+                t,
+                self.color_depth,
+                &mut self
+                    .observer
+                    .track(&mut self.cursor_style.track(&mut self.writer)),
+            ),
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        match self.frame {
+            // The frame gets flushed once it's done.
+            Some(_) => Ok(()),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+/// Implements `AnsiTerminal::frame` for a `terminal` whose `Output` is reached through `output`.
+pub(crate) fn frame<S, W, O, T, F>(
+    terminal: &mut S,
+    output: fn(&mut S) -> &mut Output<W, O>,
+    f: F,
+) -> io::Result<T>
+where
+    W: Write,
+    O: Observe,
+    F: FnOnce(&mut S) -> io::Result<T>,
+{
+    if output(terminal).frame.is_some() {
+        return f(terminal);
+    }
+    output(terminal).frame = Some(Vec::new());
+    let result = output(terminal)
+        .write(&AnsiEscape::BeginSynchronizedUpdate)
+        .and_then(|()| f(terminal));
+    // Even if `f` failed, what it managed to write shouldn't be held up.
+    let output = output(terminal);
+    let ended = output.write(&AnsiEscape::EndSynchronizedUpdate);
+    let frame = output.frame.take().unwrap_or_default();
+    let written = ended
+        .and_then(|()| output.writer.write_all(&frame))
+        .and_then(|()| output.writer.flush());
+    result.and_then(|t| written.map(|()| t))
+}
@@ -1,10 +1,21 @@
 pub use crate::{
     ansi_terminal,
     escapes::{
-        cursor::CursorEscape::*,
-        formatting::{ColorTableValue, PresetColor::*, Rgb, SetGraphicsRenditionEscape::*},
+        cursor::{CursorEscape::*, CursorShape},
+        erase::{DisplayErasure, LineErasure},
+        formatting::{
+            BlinkSpeed, ColorTableValue, PresetColor::*, Rgb, SetGraphicsRenditionEscape::*,
+            UnderlineStyle,
+        },
+        mouse::{MouseEncoding, MouseTrackingMode},
+        osc::{ClipboardTarget, Hyperlink, OscEscape::*, OscTerminator, TerminatedOsc},
         AnsiEscape::*,
     },
+    input::{
+        keyboard::{KeyCode, KeyEvent, KeyModifiers},
+        mouse::{MouseButton, MouseEvent, MouseEventKind},
+        Event,
+    },
     out,
     AnsiTerminal, TerminalModeOptions,
 };
@@ -0,0 +1,878 @@
+//! Reads compiled terminfo entries, which describe the escapes a terminal understands when they
+//! differ from the ones in `escapes`, like for `screen` or the Linux console.
+
+use {
+    failure::Fail,
+    std::{
+        collections::HashMap,
+        env, fs, io,
+        path::{Path, PathBuf},
+    },
+};
+
+/// The magic number for entries that store numbers in 16 bits.
+const LEGACY_MAGIC: u16 = 0o432;
+/// The magic number for entries that store numbers in 32 bits, which ncurses writes for numbers
+/// that don't fit in 16.
+const EXTENDED_NUMBERS_MAGIC: u16 = 0o1036;
+
+/// The widest field, or longest precision, a conversion may ask for, so that a malformed
+/// capability can't make `tparm` allocate without bound.
+const MAX_FIELD_WIDTH: usize = 4096;
+
+/// Where terminfo databases are looked for when nothing in the environment says otherwise.
+const DEFAULT_DIRECTORIES: [&str; 5] = [
+    "/etc/terminfo",
+    "/lib/terminfo",
+    "/usr/share/terminfo",
+    "/usr/lib/terminfo",
+    "/usr/share/misc/terminfo",
+];
+
+/// Represents an error encountered while loading a terminfo entry.
+#[derive(Debug, Fail)]
+pub enum TermInfoError {
+    #[fail(display = "TERM is not set")]
+    TermNotSet,
+    #[fail(display = "no terminfo entry found for {:?}", _0)]
+    NotFound(String),
+    #[fail(display = "unable to read terminfo entry: {}", _0)]
+    Io(io::Error),
+    #[fail(display = "malformed terminfo entry: {}", _0)]
+    Malformed(&'static str),
+}
+
+/// Represents an error encountered while expanding the parameters of a string capability with
+/// `tparm`.
+#[derive(Debug, Fail)]
+pub enum ExpandError {
+    #[fail(display = "at most 9 parameters can be given, but {} were", _0)]
+    TooManyParameters(usize),
+    #[fail(display = "an operator needed a number, but got a string, or the other way around")]
+    TypeMismatch,
+    #[fail(display = "malformed parameterized string: {}", _0)]
+    Malformed(&'static str),
+}
+
+/// A terminal description loaded from the terminfo database. Capabilities are looked up by their
+/// short names, like `cup` for moving the cursor; extended capabilities, like `Smulx` or `Tc`,
+/// are looked up the same way.
+#[derive(Clone, Debug)]
+pub struct TermInfo {
+    names: Vec<String>,
+    booleans: HashMap<String, bool>,
+    numbers: HashMap<String, i32>,
+    strings: HashMap<String, Vec<u8>>,
+}
+
+impl TermInfo {
+    /// Loads the entry for the terminal named by `TERM`.
+    pub fn from_env() -> Result<Self, TermInfoError> {
+        match env::var("TERM") {
+            Ok(name) if !name.is_empty() => TermInfo::from_name(&name),
+            _ => Err(TermInfoError::TermNotSet),
+        }
+    }
+
+    /// Loads the entry for the terminal called `name`, looking through `TERMINFO`,
+    /// `~/.terminfo`, `TERMINFO_DIRS` and then the usual system directories, like ncurses does.
+    /// An empty entry in `TERMINFO_DIRS` stands for the system directories.
+    pub fn from_name(name: &str) -> Result<Self, TermInfoError> {
+        let first = match name.chars().next() {
+            Some(c) if !name.contains('/') => c,
+            _ => return Err(TermInfoError::NotFound(name.to_owned())),
+        };
+        for directory in search_directories() {
+            // Some systems, like macOS, name the subdirectories by the hexadecimal value of the
+            // first character instead.
+            for subdirectory in [first.to_string(), format!("{:x}", u32::from(first))].iter() {
+                let path = directory.join(subdirectory).join(name);
+                if path.is_file() {
+                    return TermInfo::from_path(path);
+                }
+            }
+        }
+        Err(TermInfoError::NotFound(name.to_owned()))
+    }
+
+    /// Loads the compiled entry at `path`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, TermInfoError> {
+        TermInfo::parse(&fs::read(path).map_err(TermInfoError::Io)?)
+    }
+
+    /// Parses a compiled entry, in either the legacy format or the one with 32-bit numbers,
+    /// along with the extended capabilities that may follow it.
+    pub fn parse(bytes: &[u8]) -> Result<Self, TermInfoError> {
+        let mut reader = EntryReader { bytes, position: 0 };
+        let number_size = match reader.u16()? {
+            LEGACY_MAGIC => 2,
+            EXTENDED_NUMBERS_MAGIC => 4,
+            _ => return Err(TermInfoError::Malformed("unknown magic number")),
+        };
+        let names_size = reader.count()?;
+        let boolean_count = reader.count()?;
+        let number_count = reader.count()?;
+        let string_count = reader.count()?;
+        let string_table_size = reader.count()?;
+        if boolean_count > BOOLEAN_NAMES.len()
+            || number_count > NUMBER_NAMES.len()
+            || string_count > STRING_NAMES.len()
+        {
+            return Err(TermInfoError::Malformed(
+                "more standard capabilities than exist",
+            ));
+        }
+
+        let names = reader.take(names_size)?;
+        let names = names.split(|&b| b == 0).next().unwrap_or_default();
+        let mut info = TermInfo {
+            names: String::from_utf8_lossy(names)
+                .split('|')
+                .map(str::to_owned)
+                .collect(),
+            booleans: HashMap::new(),
+            numbers: HashMap::new(),
+            strings: HashMap::new(),
+        };
+
+        let booleans = reader.take(boolean_count)?;
+        reader.align();
+        let numbers = reader.numbers(number_count, number_size)?;
+        let offsets = reader.offsets(string_count)?;
+        let table = reader.take(string_table_size)?;
+        for (name, &value) in BOOLEAN_NAMES.iter().zip(booleans) {
+            if value == 1 {
+                info.booleans.insert((*name).to_owned(), true);
+            }
+        }
+        for (name, value) in NUMBER_NAMES.iter().zip(numbers) {
+            if let Some(value) = value {
+                info.numbers.insert((*name).to_owned(), value);
+            }
+        }
+        for (name, offset) in STRING_NAMES.iter().zip(offsets) {
+            if let Some(offset) = offset {
+                info.strings
+                    .insert((*name).to_owned(), string_at(table, offset)?.to_owned());
+            }
+        }
+
+        reader.align();
+        if reader.position < bytes.len() {
+            info.parse_extended(&mut reader, number_size)?;
+        }
+        Ok(info)
+    }
+
+    /// Parses the extended capabilities, which carry their names with them.
+    fn parse_extended(
+        &mut self,
+        reader: &mut EntryReader,
+        number_size: usize,
+    ) -> Result<(), TermInfoError> {
+        let boolean_count = reader.count()?;
+        let number_count = reader.count()?;
+        let string_count = reader.count()?;
+        let _table_items = reader.count()?;
+        let table_size = reader.count()?;
+
+        let booleans = reader.take(boolean_count)?;
+        reader.align();
+        let numbers = reader.numbers(number_count, number_size)?;
+        let offsets = reader.offsets(string_count)?;
+        let name_offsets = reader.offsets(boolean_count + number_count + string_count)?;
+        let table = reader.take(table_size)?;
+
+        // Names follow the last of the values in the table, and their offsets start from there.
+        let mut names_start = 0;
+        for offset in offsets.iter().flatten() {
+            names_start = names_start.max(offset + string_at(table, *offset)?.len() + 1);
+        }
+        let names_table = table
+            .get(names_start..)
+            .ok_or(TermInfoError::Malformed("string offset out of bounds"))?;
+        let mut names = name_offsets.into_iter().map(|offset| match offset {
+            Some(offset) => {
+                Ok(String::from_utf8_lossy(string_at(names_table, offset)?).into_owned())
+            }
+            None => Err(TermInfoError::Malformed("missing extended capability name")),
+        });
+
+        for &value in booleans {
+            let name = names.next().unwrap()?;
+            if value == 1 {
+                self.booleans.insert(name, true);
+            }
+        }
+        for value in numbers {
+            let name = names.next().unwrap()?;
+            if let Some(value) = value {
+                self.numbers.insert(name, value);
+            }
+        }
+        for offset in offsets {
+            let name = names.next().unwrap()?;
+            if let Some(offset) = offset {
+                self.strings
+                    .insert(name, string_at(table, offset)?.to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the names the terminal goes by, with the description last.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Gets whether the terminal has the boolean capability `name`.
+    pub fn boolean(&self, name: &str) -> bool {
+        self.booleans.get(name).cloned().unwrap_or(false)
+    }
+
+    /// Gets the number capability `name`, like `colors`.
+    pub fn number(&self, name: &str) -> Option<i32> {
+        self.numbers.get(name).cloned()
+    }
+
+    /// Gets the string capability `name` as it's stored. Ones that take parameters need to be
+    /// expanded with `tparm`.
+    pub fn string(&self, name: &str) -> Option<&[u8]> {
+        self.strings.get(name).map(Vec::as_slice)
+    }
+}
+
+fn search_directories() -> Vec<PathBuf> {
+    let mut directories = Vec::new();
+    if let Some(terminfo) = env::var_os("TERMINFO") {
+        directories.push(PathBuf::from(terminfo));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        directories.push(Path::new(&home).join(".terminfo"));
+    }
+    let defaults = DEFAULT_DIRECTORIES.iter().map(PathBuf::from);
+    match env::var_os("TERMINFO_DIRS") {
+        Some(dirs) => {
+            for directory in env::split_paths(&dirs) {
+                match directory.as_os_str().is_empty() {
+                    true => directories.extend(defaults.clone()),
+                    false => directories.push(directory),
+                }
+            }
+        }
+        None => directories.extend(defaults),
+    }
+    directories
+}
+
+/// Gets the NUL-terminated string starting at `offset` in `table`.
+fn string_at(table: &[u8], offset: usize) -> Result<&[u8], TermInfoError> {
+    let rest = table
+        .get(offset..)
+        .ok_or(TermInfoError::Malformed("string offset out of bounds"))?;
+    let end = rest
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(TermInfoError::Malformed("unterminated string"))?;
+    Ok(&rest[..end])
+}
+
+struct EntryReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> EntryReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], TermInfoError> {
+        let taken = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or(TermInfoError::Malformed("entry ended early"))?;
+        self.position += count;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, TermInfoError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Reads a count or size from a header, which can't be negative.
+    fn count(&mut self) -> Result<usize, TermInfoError> {
+        match self.u16()? as i16 {
+            n if n >= 0 => Ok(n as usize),
+            _ => Err(TermInfoError::Malformed("negative count in header")),
+        }
+    }
+
+    /// Skips the padding byte that keeps what follows aligned to 2 bytes.
+    fn align(&mut self) {
+        self.position += self.position % 2;
+    }
+
+    /// Reads numbers, leaving out absent and cancelled ones, which are negative.
+    fn numbers(&mut self, count: usize, size: usize) -> Result<Vec<Option<i32>>, TermInfoError> {
+        let bytes = self.take(count * size)?;
+        Ok(bytes
+            .chunks(size)
+            .map(|n| match n {
+                [a, b] => i32::from(i16::from_le_bytes([*a, *b])),
+                _ => i32::from_le_bytes([n[0], n[1], n[2], n[3]]),
+            })
+            .map(|n| if n >= 0 { Some(n) } else { None })
+            .collect())
+    }
+
+    /// Reads string table offsets, leaving out absent and cancelled ones, which are negative.
+    fn offsets(&mut self, count: usize) -> Result<Vec<Option<usize>>, TermInfoError> {
+        Ok(self
+            .numbers(count, 2)?
+            .into_iter()
+            .map(|n| n.map(|n| n as usize))
+            .collect())
+    }
+}
+
+/// A parameter for `tparm`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Parameter {
+    Number(i32),
+    String(Vec<u8>),
+}
+
+impl Default for Parameter {
+    fn default() -> Self {
+        Parameter::Number(0)
+    }
+}
+
+impl From<i32> for Parameter {
+    fn from(n: i32) -> Self {
+        Parameter::Number(n)
+    }
+}
+
+impl<'a> From<&'a str> for Parameter {
+    fn from(s: &'a str) -> Self {
+        Parameter::String(s.as_bytes().to_owned())
+    }
+}
+
+/// Expands the `%` escapes in a string capability with `parameters`, like the function of the
+/// same name in curses. For instance, `tparm(info.string("cup").unwrap(), &[row.into(),
+/// column.into()])` makes the escape that moves the cursor. Variables set with `%P` start out as
+/// 0 every time.
+pub fn tparm(capability: &[u8], parameters: &[Parameter]) -> Result<Vec<u8>, ExpandError> {
+    if parameters.len() > 9 {
+        return Err(ExpandError::TooManyParameters(parameters.len()));
+    }
+    let mut params: [Parameter; 9] = Default::default();
+    params[..parameters.len()].clone_from_slice(parameters);
+    let mut variables: [Parameter; 52] = [(); 52].map(|_| Parameter::default());
+    let mut stack = Stack(Vec::new());
+    let mut output = Vec::new();
+
+    let mut i = 0;
+    while let Some(&c) = capability.get(i) {
+        i += 1;
+        if c != b'%' {
+            output.push(c);
+            continue;
+        }
+        let mut next = || {
+            let c = capability.get(i).cloned();
+            i += 1;
+            c.ok_or(ExpandError::Malformed(
+                "string ended in the middle of an escape",
+            ))
+        };
+        match next()? {
+            b'%' => output.push(b'%'),
+            b'c' => output.push(stack.number()? as u8),
+            b'p' => match next()? {
+                d @ b'1'..=b'9' => stack.0.push(params[usize::from(d - b'1')].clone()),
+                _ => return Err(ExpandError::Malformed("bad parameter number")),
+            },
+            b'P' => {
+                let variable = variable(next()?)?;
+                variables[variable] = stack.pop()?;
+            }
+            b'g' => {
+                let variable = variable(next()?)?;
+                stack.0.push(variables[variable].clone());
+            }
+            b'\'' => {
+                let c = next()?;
+                if next()? != b'\'' {
+                    return Err(ExpandError::Malformed("unterminated character constant"));
+                }
+                stack.0.push(Parameter::Number(i32::from(c)));
+            }
+            b'{' => {
+                let mut n: i32 = 0;
+                loop {
+                    match next()? {
+                        b'}' => break,
+                        d @ b'0'..=b'9' => n = n.wrapping_mul(10).wrapping_add(i32::from(d - b'0')),
+                        _ => return Err(ExpandError::Malformed("bad integer constant")),
+                    }
+                }
+                stack.0.push(Parameter::Number(n));
+            }
+            b'l' => {
+                let length = stack.string()?.len();
+                stack.0.push(Parameter::Number(length as i32));
+            }
+            op @ (b'+' | b'-' | b'*' | b'/' | b'm' | b'&' | b'|' | b'^' | b'=' | b'>' | b'<'
+            | b'A' | b'O') => {
+                let b = stack.number()?;
+                let a = stack.number()?;
+                stack.0.push(Parameter::Number(match op {
+                    b'+' => a.wrapping_add(b),
+                    b'-' => a.wrapping_sub(b),
+                    b'*' => a.wrapping_mul(b),
+                    b'/' => a.checked_div(b).unwrap_or(0),
+                    b'm' => a.checked_rem(b).unwrap_or(0),
+                    b'&' => a & b,
+                    b'|' => a | b,
+                    b'^' => a ^ b,
+                    b'=' => (a == b) as i32,
+                    b'>' => (a > b) as i32,
+                    b'<' => (a < b) as i32,
+                    b'A' => (a != 0 && b != 0) as i32,
+                    _ => (a != 0 || b != 0) as i32,
+                }));
+            }
+            b'!' => {
+                let n = stack.number()?;
+                stack.0.push(Parameter::Number((n == 0) as i32));
+            }
+            b'~' => {
+                let n = stack.number()?;
+                stack.0.push(Parameter::Number(!n));
+            }
+            b'i' => {
+                for param in &mut params[..2] {
+                    if let Parameter::Number(n) = param {
+                        *n = n.wrapping_add(1);
+                    }
+                }
+            }
+            b'?' | b';' => (),
+            b't' => {
+                let condition = stack.number()?;
+                if condition == 0 {
+                    i = skip_branch(capability, i, true);
+                }
+            }
+            // Getting here means the branch before was taken.
+            b'e' => i = skip_branch(capability, i, false),
+            c @ (b':' | b'#' | b' ' | b'.' | b'0'..=b'9' | b'd' | b'o' | b'x' | b'X' | b's') => {
+                let mut format = Format::default();
+                i = format.parse(capability, i - 1, c)?;
+                let formatted = match format.conversion {
+                    b's' => stack.string()?,
+                    _ => format.number(stack.number()?),
+                };
+                format.pad(formatted, &mut output);
+            }
+            // Like curses, leave out escapes that aren't known.
+            _ => (),
+        }
+    }
+    Ok(output)
+}
+
+/// Finds where execution continues after skipping the branch of a conditional that starts at
+/// `i`. Skipping stops after the `%;` that ends the conditional, or after the next `%e` at the
+/// same level if `to_else` is set.
+fn skip_branch(capability: &[u8], mut i: usize, to_else: bool) -> usize {
+    let mut level = 0;
+    while i + 1 < capability.len() {
+        if capability[i] != b'%' {
+            i += 1;
+            continue;
+        }
+        i += 2;
+        match capability[i - 1] {
+            b'?' => level += 1,
+            b';' if level == 0 => return i,
+            b';' => level -= 1,
+            b'e' if level == 0 && to_else => return i,
+            _ => (),
+        }
+    }
+    capability.len()
+}
+
+fn variable(name: u8) -> Result<usize, ExpandError> {
+    match name {
+        b'a'..=b'z' => Ok(usize::from(name - b'a')),
+        b'A'..=b'Z' => Ok(usize::from(name - b'A') + 26),
+        _ => Err(ExpandError::Malformed("bad variable name")),
+    }
+}
+
+struct Stack(Vec<Parameter>);
+
+impl Stack {
+    /// Pops the top of the stack. Like curses, an empty stack gives 0, which some entries count on.
+    fn pop(&mut self) -> Result<Parameter, ExpandError> {
+        Ok(self.0.pop().unwrap_or_default())
+    }
+
+    fn number(&mut self) -> Result<i32, ExpandError> {
+        match self.pop()? {
+            Parameter::Number(n) => Ok(n),
+            Parameter::String(_) => Err(ExpandError::TypeMismatch),
+        }
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, ExpandError> {
+        match self.pop()? {
+            Parameter::String(s) => Ok(s),
+            Parameter::Number(_) => Err(ExpandError::TypeMismatch),
+        }
+    }
+}
+
+/// A `printf`-style conversion, written as `%[[:]flags][width[.precision]][doxXs]`.
+#[derive(Default)]
+struct Format {
+    left_align: bool,
+    sign: bool,
+    space: bool,
+    alternate: bool,
+    zero_pad: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: u8,
+}
+
+impl Format {
+    /// Parses the conversion that starts with `first` at `i`, returning where it ends.
+    fn parse(&mut self, capability: &[u8], mut i: usize, first: u8) -> Result<usize, ExpandError> {
+        if first == b':' {
+            i += 1;
+        }
+        while let Some(&c) = capability.get(i) {
+            match c {
+                b'-' => self.left_align = true,
+                b'+' => self.sign = true,
+                b' ' => self.space = true,
+                b'#' => self.alternate = true,
+                b'0' => self.zero_pad = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        let digits = |i: &mut usize| {
+            let mut n: usize = 0;
+            while let Some(d @ b'0'..=b'9') = capability.get(*i) {
+                n = n
+                    .checked_mul(10)
+                    .and_then(|n| n.checked_add(usize::from(d - b'0')))
+                    .filter(|&n| n <= MAX_FIELD_WIDTH)
+                    .ok_or(ExpandError::Malformed("field width too large"))?;
+                *i += 1;
+            }
+            Ok(n)
+        };
+        self.width = digits(&mut i)?;
+        if capability.get(i) == Some(&b'.') {
+            i += 1;
+            self.precision = Some(digits(&mut i)?);
+        }
+        match capability.get(i) {
+            Some(&c @ b'd') | Some(&c @ b'o') | Some(&c @ b'x') | Some(&c @ b'X')
+            | Some(&c @ b's') => {
+                self.conversion = c;
+                Ok(i + 1)
+            }
+            _ => Err(ExpandError::Malformed("unknown escape")),
+        }
+    }
+
+    fn number(&self, n: i32) -> Vec<u8> {
+        // Like C's printf, only decimal conversions are signed.
+        let (sign, digits) = match self.conversion {
+            b'd' if n < 0 => ("-", n.unsigned_abs().to_string()),
+            b'd' if self.sign => ("+", n.to_string()),
+            b'd' if self.space => (" ", n.to_string()),
+            b'd' => ("", n.to_string()),
+            b'o' if self.alternate && n != 0 => ("0", format!("{:o}", n)),
+            b'o' => ("", format!("{:o}", n)),
+            b'x' if self.alternate && n != 0 => ("0x", format!("{:x}", n)),
+            b'x' => ("", format!("{:x}", n)),
+            b'X' if self.alternate && n != 0 => ("0X", format!("{:X}", n)),
+            _ => ("", format!("{:X}", n)),
+        };
+        let precision = self.precision.unwrap_or(0);
+        let zeros = match self.zero_pad && !self.left_align && self.precision.is_none() {
+            true => self.width.saturating_sub(sign.len()),
+            false => precision,
+        };
+        format!("{}{:0>width$}", sign, digits, width = zeros).into_bytes()
+    }
+
+    fn pad(&self, mut formatted: Vec<u8>, output: &mut Vec<u8>) {
+        if let (b's', Some(precision)) = (self.conversion, self.precision) {
+            formatted.truncate(precision);
+        }
+        let padding = self.width.saturating_sub(formatted.len());
+        if !self.left_align {
+            output.extend(std::iter::repeat_n(b' ', padding));
+        }
+        output.extend(formatted);
+        if self.left_align {
+            output.extend(std::iter::repeat_n(b' ', padding));
+        }
+    }
+}
+
+/// The names of the standard boolean capabilities, in the order compiled entries store them.
+const BOOLEAN_NAMES: [&str; 44] = [
+    "bw", "am", "xsb", "xhp", "xenl", "eo", "gn", "hc", "km", "hs", "in", "da", "db", "mir",
+    "msgr", "os", "eslok", "xt", "hz", "ul", "xon", "nxon", "mc5i", "chts", "nrrmc", "npc",
+    "ndscr", "ccc", "bce", "hls", "xhpa", "crxm", "daisy", "xvpa", "sam", "cpix", "lpix", "OTbs",
+    "OTns", "OTnc", "OTMT", "OTNL", "OTpt", "OTxr",
+];
+
+/// The names of the standard number capabilities, in the order compiled entries store them.
+const NUMBER_NAMES: [&str; 39] = [
+    "cols", "it", "lines", "lm", "xmc", "pb", "vt", "wsl", "nlab", "lh", "lw", "ma", "wnum",
+    "colors", "pairs", "ncv", "bufsz", "spinv", "spinh", "maddr", "mjump", "mcs", "mls", "npins",
+    "orc", "orl", "orhi", "orvi", "cps", "widcs", "btns", "bitwin", "bitype", "OTug", "OTdC",
+    "OTdN", "OTdB", "OTdT", "OTkn",
+];
+
+/// The names of the standard string capabilities, in the order compiled entries store them.
+const STRING_NAMES: [&str; 414] = [
+    "cbt", "bel", "cr", "csr", "tbc", "clear", "el", "ed", "hpa", "cmdch", "cup", "cud1", "home",
+    "civis", "cub1", "mrcup", "cnorm", "cuf1", "ll", "cuu1", "cvvis", "dch1", "dl1", "dsl", "hd",
+    "smacs", "blink", "bold", "smcup", "smdc", "dim", "smir", "invis", "prot", "rev", "smso",
+    "smul", "ech", "rmacs", "sgr0", "rmcup", "rmdc", "rmir", "rmso", "rmul", "flash", "ff", "fsl",
+    "is1", "is2", "is3", "if", "ich1", "il1", "ip", "kbs", "ktbc", "kclr", "kctab", "kdch1",
+    "kdl1", "kcud1", "krmir", "kel", "ked", "kf0", "kf1", "kf10", "kf2", "kf3", "kf4", "kf5",
+    "kf6", "kf7", "kf8", "kf9", "khome", "kich1", "kil1", "kcub1", "kll", "knp", "kpp", "kcuf1",
+    "kind", "kri", "khts", "kcuu1", "rmkx", "smkx", "lf0", "lf1", "lf10", "lf2", "lf3", "lf4",
+    "lf5", "lf6", "lf7", "lf8", "lf9", "rmm", "smm", "nel", "pad", "dch", "dl", "cud", "ich",
+    "indn", "il", "cub", "cuf", "rin", "cuu", "pfkey", "pfloc", "pfx", "mc0", "mc4", "mc5", "rep",
+    "rs1", "rs2", "rs3", "rf", "rc", "vpa", "sc", "ind", "ri", "sgr", "hts", "wind", "ht", "tsl",
+    "uc", "hu", "iprog", "ka1", "ka3", "kb2", "kc1", "kc3", "mc5p", "rmp", "acsc", "pln", "kcbt",
+    "smxon", "rmxon", "smam", "rmam", "xonc", "xoffc", "enacs", "smln", "rmln", "kbeg", "kcan",
+    "kclo", "kcmd", "kcpy", "kcrt", "kend", "kent", "kext", "kfnd", "khlp", "kmrk", "kmsg", "kmov",
+    "knxt", "kopn", "kopt", "kprv", "kprt", "krdo", "kref", "krfr", "krpl", "krst", "kres", "ksav",
+    "kspd", "kund", "kBEG", "kCAN", "kCMD", "kCPY", "kCRT", "kDC", "kDL", "kslt", "kEND", "kEOL",
+    "kEXT", "kFND", "kHLP", "kHOM", "kIC", "kLFT", "kMSG", "kMOV", "kNXT", "kOPT", "kPRV", "kPRT",
+    "kRDO", "kRPL", "kRIT", "kRES", "kSAV", "kSPD", "kUND", "rfi", "kf11", "kf12", "kf13", "kf14",
+    "kf15", "kf16", "kf17", "kf18", "kf19", "kf20", "kf21", "kf22", "kf23", "kf24", "kf25", "kf26",
+    "kf27", "kf28", "kf29", "kf30", "kf31", "kf32", "kf33", "kf34", "kf35", "kf36", "kf37", "kf38",
+    "kf39", "kf40", "kf41", "kf42", "kf43", "kf44", "kf45", "kf46", "kf47", "kf48", "kf49", "kf50",
+    "kf51", "kf52", "kf53", "kf54", "kf55", "kf56", "kf57", "kf58", "kf59", "kf60", "kf61", "kf62",
+    "kf63", "el1", "mgc", "smgl", "smgr", "fln", "sclk", "dclk", "rmclk", "cwin", "wingo", "hup",
+    "dial", "qdial", "tone", "pulse", "hook", "pause", "wait", "u0", "u1", "u2", "u3", "u4", "u5",
+    "u6", "u7", "u8", "u9", "op", "oc", "initc", "initp", "scp", "setf", "setb", "cpi", "lpi",
+    "chr", "cvr", "defc", "swidm", "sdrfq", "sitm", "slm", "smicm", "snlq", "snrmq", "sshm",
+    "ssubm", "ssupm", "sum", "rwidm", "ritm", "rlm", "rmicm", "rshm", "rsubm", "rsupm", "rum",
+    "mhpa", "mcud1", "mcub1", "mcuf1", "mvpa", "mcuu1", "porder", "mcud", "mcub", "mcuf", "mcuu",
+    "scs", "smgb", "smgbp", "smglp", "smgrp", "smgt", "smgtp", "sbim", "scsd", "rbim", "rcsd",
+    "subcs", "supcs", "docr", "zerom", "csnm", "kmous", "minfo", "reqmp", "getm", "setaf", "setab",
+    "pfxl", "devt", "csin", "s0ds", "s1ds", "s2ds", "s3ds", "smglr", "smgtb", "birep", "binel",
+    "bicr", "colornm", "defbi", "endbi", "setcolor", "slines", "dispc", "smpch", "rmpch", "smsc",
+    "rmsc", "pctrm", "scesc", "scesa", "ehhlm", "elhlm", "elohlm", "erhlm", "ethlm", "evhlm",
+    "sgr1", "slength", "OTi2", "OTrs", "OTnl", "OTbc", "OTko", "OTma", "OTG2", "OTG3", "OTG1",
+    "OTG4", "OTGR", "OTGL", "OTGU", "OTGD", "OTGH", "OTGV", "OTGC", "meml", "memu", "box1",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Compiled with `tic -x` from:
+    //
+    //     test-legacy|a legacy test terminal,
+    //         am, xenl, colors#8, cols#80, lines#24,
+    //         bold=\E[1m, cup=\E[%i%p1%d;%p2%dH,
+    //         Tc, U8#1, Smulx=\E[4:%p1%dm,
+    const LEGACY_ENTRY: &[u8] = b"\x1A\x01#\x00\x05\x00\x0E\x00\x1C\x00\x16\x00\
+        test-legacy|a legacy test terminal\
+        \x00\x00\x01\x00\x00\x01P\x00\xFF\xFF\x18\x00\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\
+        \xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x08\x00\xFF\xFF\xFF\xFF\xFF\
+        \xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x00\x00\xFF\xFF\
+        \xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\
+        \xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x11\x00\x1B[%i%p1%d;%p2%dH\x00\x1B[1m\
+        \x00\x01\x00\x01\x00\x01\x00\x04\x00\x17\x00\x01\x00\x01\x00\x00\x00\x00\x00\
+        \x03\x00\x06\x00\x1B[4:%p1%dm\x00Tc\x00U8\x00Smulx\x00";
+
+    // Compiled with `tic -x` from the following, whose numbers don't fit in 16 bits:
+    //
+    //     test-wide|a test terminal with 32-bit numbers,
+    //         am, colors#0x1000000, cols#80, pairs#0x10000,
+    //         bold=\E[1m, cup=\E[%i%p1%d;%p2%dH,
+    //         Tc, Smulx=\E[4:%p1%dm,
+    const WIDE_ENTRY: &[u8] = b"\x1E\x02.\x00\x02\x00\x0F\x00\x1C\x00\x16\x00\
+        test-wide|a test terminal with 32-bit numbers\
+        \x00\x00\x01P\x00\x00\x00\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\
+        \xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\
+        \xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x00\x00\x00\
+        \x01\x00\x00\x01\x00\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\
+        \xFF\xFF\xFF\xFF\xFF\xFF\x00\x00\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\
+        \xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\
+        \xFF\xFF\x11\x00\x1B[%i%p1%d;%p2%dH\x00\x1B[1m\x00\x01\x00\x00\x00\x01\x00\x03\
+        \x00\x14\x00\x01\x00\x00\x00\x00\x00\x03\x00\x1B[4:%p1%dm\x00Tc\x00Smulx\x00";
+
+    #[test]
+    fn parses_legacy_entries() {
+        let info = TermInfo::parse(LEGACY_ENTRY).unwrap();
+        assert_eq!(info.names(), ["test-legacy", "a legacy test terminal"]);
+        assert!(info.boolean("am"));
+        assert!(info.boolean("xenl"));
+        assert!(!info.boolean("bce"));
+        assert_eq!(info.number("colors"), Some(8));
+        assert_eq!(info.number("cols"), Some(80));
+        assert_eq!(info.number("lines"), Some(24));
+        assert_eq!(info.number("pairs"), None);
+        assert_eq!(info.string("bold"), Some(&b"\x1B[1m"[..]));
+        assert_eq!(info.string("cup"), Some(&b"\x1B[%i%p1%d;%p2%dH"[..]));
+        assert_eq!(info.string("setaf"), None);
+    }
+
+    #[test]
+    fn parses_extended_capabilities() {
+        let legacy = TermInfo::parse(LEGACY_ENTRY).unwrap();
+        assert!(legacy.boolean("Tc"));
+        assert_eq!(legacy.number("U8"), Some(1));
+        assert_eq!(legacy.string("Smulx"), Some(&b"\x1B[4:%p1%dm"[..]));
+
+        let wide = TermInfo::parse(WIDE_ENTRY).unwrap();
+        assert!(wide.boolean("Tc"));
+        assert_eq!(wide.string("Smulx"), Some(&b"\x1B[4:%p1%dm"[..]));
+    }
+
+    #[test]
+    fn parses_32_bit_numbers() {
+        let info = TermInfo::parse(WIDE_ENTRY).unwrap();
+        assert_eq!(
+            info.names(),
+            ["test-wide", "a test terminal with 32-bit numbers"]
+        );
+        assert!(info.boolean("am"));
+        assert_eq!(info.number("colors"), Some(0x100_0000));
+        assert_eq!(info.number("pairs"), Some(0x1_0000));
+        assert_eq!(info.number("cols"), Some(80));
+        assert_eq!(info.string("cup"), Some(&b"\x1B[%i%p1%d;%p2%dH"[..]));
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        let malformed =
+            |bytes: &[u8]| matches!(TermInfo::parse(bytes), Err(TermInfoError::Malformed(_)));
+        assert!(malformed(b""));
+        assert!(malformed(b"\x1A\x02\x00\x00"));
+        assert!(malformed(&LEGACY_ENTRY[..100]));
+        let mut bad_offset = LEGACY_ENTRY.to_vec();
+        // The offset of `cup`, the first string
+        let cup = 12 + 0x23 + 5 + 14 * 2 + 10 * 2;
+        bad_offset[cup] = 0x40;
+        assert!(malformed(&bad_offset));
+    }
+
+    fn expand(capability: &str, parameters: &[Parameter]) -> String {
+        String::from_utf8(tparm(capability.as_bytes(), parameters).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn expands_cursor_movement() {
+        let cup = "\x1B[%i%p1%d;%p2%dH";
+        assert_eq!(expand(cup, &[4.into(), 9.into()]), "\x1B[5;10H");
+        assert_eq!(expand(cup, &[0.into(), 0.into()]), "\x1B[1;1H");
+        // Missing parameters are 0.
+        assert_eq!(expand(cup, &[]), "\x1B[1;1H");
+    }
+
+    #[test]
+    fn expands_conditionals() {
+        // xterm-256color's `setaf`
+        let setaf = "\x1B[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m";
+        assert_eq!(expand(setaf, &[1.into()]), "\x1B[31m");
+        assert_eq!(expand(setaf, &[12.into()]), "\x1B[94m");
+        assert_eq!(expand(setaf, &[200.into()]), "\x1B[38;5;200m");
+        assert_eq!(expand("%?%p1%tyes%;", &[0.into()]), "");
+        assert_eq!(expand("%?%p1%!%tno%eyes%;", &[5.into()]), "yes");
+    }
+
+    #[test]
+    fn expands_arithmetic() {
+        let rgb = "%p1%{65536}%/%d:%p1%{256}%/%{255}%&%d:%p1%{255}%&%d";
+        assert_eq!(expand(rgb, &[0x12_3456.into()]), "18:52:86");
+        assert_eq!(
+            expand(
+                "%p1%p2%+%d %p1%p2%-%d %p1%p2%*%d %p1%p2%m%d",
+                &[7.into(), 3.into()]
+            ),
+            "10 4 21 1"
+        );
+        assert_eq!(
+            expand("%p1%p2%|%d %p1%p2%^%d %p1%~%d", &[12.into(), 10.into()]),
+            "14 6 -13"
+        );
+        assert_eq!(
+            expand(
+                "%p1%p2%>%d%p1%p2%=%d%p1%p2%A%d%p1%{0}%O%d",
+                &[2.into(), 1.into()]
+            ),
+            "1011"
+        );
+        assert_eq!(expand("%'A'%p1%+%c", &[2.into()]), "C");
+    }
+
+    #[test]
+    fn expands_strings_and_variables() {
+        assert_eq!(expand("%p1%s=%p1%l%d", &["title".into()]), "title=5");
+        assert_eq!(
+            expand("%p1%Pa%p2%PB%gB%ga%-%d", &[3.into(), 10.into()]),
+            "7"
+        );
+        assert_eq!(
+            expand("%%%p1%c", &[Parameter::Number(i32::from(b'x'))]),
+            "%x"
+        );
+    }
+
+    #[test]
+    fn expands_printf_formats() {
+        assert_eq!(
+            expand("%p1%03d|%p1%:-4d|%p1%x|%p1%#X|%p1%o", &[42.into()]),
+            "042|42  |2a|0X2A|52"
+        );
+        assert_eq!(expand("%p1%5s|%p1%:-3s|", &["ab".into()]), "   ab|ab |");
+    }
+
+    #[test]
+    fn expand_errors() {
+        let too_many = vec![Parameter::Number(0); 10];
+        match tparm(b"", &too_many) {
+            Err(ExpandError::TooManyParameters(10)) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+        match tparm(b"%p1%d", &["text".into()]) {
+            Err(ExpandError::TypeMismatch) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+        match tparm(b"%p1%99999999999999999999999d", &[0.into()]) {
+            Err(ExpandError::Malformed(_)) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+        match tparm(b"%p1%.5000d", &[0.into()]) {
+            Err(ExpandError::Malformed(_)) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+        // `%i` wraps like the other arithmetic rather than overflowing.
+        let max = Parameter::Number(i32::MAX);
+        assert_eq!(
+            tparm(b"%i%p1%d;%p2%d", &[max.clone(), max]).unwrap(),
+            format!("{};{}", i32::MIN, i32::MIN).into_bytes()
+        );
+    }
+}
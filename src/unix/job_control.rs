@@ -0,0 +1,74 @@
+use {
+    super::{
+        restore::{write_all, Restore},
+        take_pending,
+    },
+    crate::{escapes::AnsiEscape, TerminalOutput},
+    signal_hook::{
+        consts::SIGTSTP,
+        low_level::{emulate_default_handler, register, unregister},
+        SigId,
+    },
+    std::{
+        io,
+        os::unix::{io::AsRawFd, net::UnixStream},
+        sync::atomic::Ordering,
+    },
+    termios::{tcsetattr, Termios, TCSANOW},
+};
+
+/// Hands the terminal back to the shell in the state it was found in when the process is
+/// suspended with `SIGTSTP`, and takes it over again once the process is continued.
+#[derive(Debug)]
+pub(crate) struct JobControl {
+    id: SigId,
+    /// Receives a byte every time the process is continued.
+    pub(crate) receiver: UnixStream,
+}
+
+impl JobControl {
+    pub(crate) fn register(restore: Restore) -> io::Result<Self> {
+        let (receiver, sender) = UnixStream::pair()?;
+        receiver.set_nonblocking(true)?;
+        sender.set_nonblocking(true)?;
+        let mut enter_alternate_screen = Vec::new();
+        AnsiEscape::SwitchToAlternateScreenBuffer.fmt(&mut enter_alternate_screen)?;
+
+        let id = unsafe {
+            register(SIGTSTP, move || {
+                // There can't be more descriptors than the input and output streams.
+                let mut current: [Option<Termios>; 2] = [None; 2];
+                for (current, (fd, _)) in current.iter_mut().zip(&restore.termios) {
+                    *current = Termios::from_fd(*fd).ok();
+                }
+                let alternate_screen = restore.alternate_screen.load(Ordering::SeqCst);
+
+                restore.run();
+                // This stops the process until it gets continued.
+                let _ = emulate_default_handler(SIGTSTP);
+
+                for (current, (fd, _)) in current.iter().zip(&restore.termios) {
+                    if let Some(termios) = current {
+                        let _ = tcsetattr(*fd, TCSANOW, termios);
+                    }
+                }
+                if let (true, Some(fd)) = (alternate_screen, restore.output) {
+                    write_all(fd, &enter_alternate_screen);
+                }
+                write_all(sender.as_raw_fd(), &[0]);
+            })
+        }?;
+        Ok(JobControl { id, receiver })
+    }
+
+    /// Consumes all pending notifications, returning whether there were any.
+    pub(crate) fn take_pending(&self) -> bool {
+        take_pending(&self.receiver)
+    }
+}
+
+impl Drop for JobControl {
+    fn drop(&mut self) {
+        unregister(self.id);
+    }
+}
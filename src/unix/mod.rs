@@ -0,0 +1,750 @@
+mod job_control;
+mod restore;
+
+pub use self::restore::RestoreGuard;
+
+use {
+    self::{
+        job_control::JobControl,
+        restore::{Restore, ScreenTracker},
+    },
+    crate::{
+        capabilities::{
+            Capabilities, ColorDepth, ModeState, PrimaryDeviceAttributes, SecondaryDeviceAttributes,
+        },
+        escapes::{
+            cursor::CursorEscape, formatting::SetGraphicsRenditionEscape, osc::ClipboardTarget,
+            AnsiEscape,
+        },
+        input::{
+            self,
+            keyboard::{KeyCode, KeyEvent},
+            Event, EventSource, InputState, RawInput, Received, Reply,
+        },
+        output::{self, Output},
+        AnsiTerminal, TerminalChannelMode, TerminalModeOptions, TerminalModeSetError,
+        TerminalOutput, TerminalSetupError,
+    },
+    libc::c_int,
+    signal_hook::{
+        consts::{SIGTSTP, SIGWINCH},
+        low_level::pipe,
+        SigId,
+    },
+    std::{
+        fs::{File, OpenOptions},
+        io::{self, stdin, stdout, Read, Write},
+        mem,
+        os::unix::{
+            io::{AsRawFd, RawFd},
+            net::UnixStream,
+        },
+        time::Duration,
+    },
+    termios::{
+        cfmakeraw, tcflag_t, tcsetattr, Termios, ECHO, ECHONL, ICANON, ISIG, IXON, OPOST, TCSANOW,
+        VMIN, VTIME,
+    },
+    try_from::TryFrom,
+};
+
+/// An `AnsiTerminal` that reads input from `I` and writes output to `O`, which are the process's
+/// standard streams by default.
+#[derive(Debug)]
+pub struct UnixAnsiTerminal<I: Read = io::Stdin, O: Write = io::Stdout> {
+    // These restore the terminal's state when dropped, which needs to happen before the streams
+    // that own their file descriptors get closed.
+    stdin: StdInputHandle,
+    stdout: StdOutputHandle,
+    reader: I,
+    output: Output<O, ScreenTracker>,
+    input: InputState,
+    resize_signal: Option<ResizeSignal>,
+    job_control: Option<JobControl>,
+}
+
+#[derive(Debug)]
+pub struct TerminalState {
+    file_descriptor: RawFd,
+    raw_termios: Termios,
+    cooked_termios: Termios,
+    termios_to_restore: Termios,
+}
+
+impl TerminalState {
+    /// Applies `mode`, with the overrides in `options` on top of it.
+    fn set_mode(
+        &mut self,
+        mode: TerminalChannelMode,
+        options: &TerminalModeOptions,
+    ) -> io::Result<()> {
+        use self::TerminalChannelMode::*;
+        let mut termios = match mode {
+            Raw => self.raw_termios,
+            Cooked => self.cooked_termios,
+        };
+
+        let TerminalModeOptions {
+            echo,
+            canonical,
+            signals,
+            output_processing,
+            flow_control,
+            read_minimum,
+            read_timeout,
+            ..
+        } = *options;
+        fn set_flag(flags: &mut tcflag_t, flag: tcflag_t, enabled: Option<bool>) {
+            match enabled {
+                Some(true) => *flags |= flag,
+                Some(false) => *flags &= !flag,
+                None => (),
+            }
+        }
+        set_flag(&mut termios.c_lflag, ECHO, echo);
+        set_flag(&mut termios.c_lflag, ICANON, canonical);
+        set_flag(&mut termios.c_lflag, ISIG, signals);
+        set_flag(&mut termios.c_oflag, OPOST, output_processing);
+        set_flag(&mut termios.c_iflag, IXON, flow_control);
+        if let Some(bytes) = read_minimum {
+            termios.c_cc[VMIN] = bytes;
+        }
+        if let Some(timeout) = read_timeout {
+            termios.c_cc[VTIME] = (timeout.as_millis() / 100).min(u128::from(u8::MAX)) as u8;
+        }
+
+        tcsetattr(self.file_descriptor, TCSANOW, &termios)
+    }
+}
+
+impl TryFrom<RawFd> for TerminalState {
+    type Err = io::Error;
+    fn try_from(fd: RawFd) -> Result<Self, Self::Err> {
+        let termios = Termios::from_fd(fd)?;
+
+        let cooked_termios = termios.clone();
+        let mut raw_termios = cooked_termios;
+        cfmakeraw(&mut raw_termios);
+        Ok(TerminalState {
+            file_descriptor: fd,
+            raw_termios,
+            cooked_termios,
+            termios_to_restore: termios.clone(),
+        })
+    }
+}
+
+impl Drop for TerminalState {
+    fn drop(&mut self) {
+        let _ = tcsetattr(self.file_descriptor, TCSANOW, &self.termios_to_restore);
+    }
+}
+
+#[derive(Debug)]
+pub enum Stream {
+    Tty(TerminalState),
+    NonTty(RawFd),
+    /// A stream with no file descriptor behind it, like an in-memory buffer
+    Detached,
+}
+
+impl Stream {
+    pub fn raw_fd(&self) -> Option<RawFd> {
+        match self {
+            Stream::Tty(state) => Some(state.file_descriptor),
+            Stream::NonTty(fd) => Some(*fd),
+            Stream::Detached => None,
+        }
+    }
+}
+
+impl From<RawFd> for Stream {
+    fn from(fd: RawFd) -> Stream {
+        match TerminalState::try_from(fd) {
+            Ok(state) => Stream::Tty(state),
+            Err(_) => Stream::NonTty(fd),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StdInputHandle(pub Stream);
+#[derive(Debug)]
+pub struct StdOutputHandle(pub Stream);
+
+impl UnixAnsiTerminal {
+    pub fn new() -> Result<UnixAnsiTerminal, TerminalSetupError> {
+        UnixAnsiTerminal::from_tty_streams(stdin(), stdout())
+    }
+}
+
+impl UnixAnsiTerminal<File, File> {
+    /// Opens the process's controlling terminal through `/dev/tty`, which works even when stdin
+    /// or stdout are redirected. This lets programs like fuzzy finders read keys and draw their
+    /// UI on the terminal while their results are written to stdout for a pipe.
+    pub fn open_tty() -> Result<Self, TerminalSetupError> {
+        let reader = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .map_err(TerminalSetupError::Tty)?;
+        let writer = reader.try_clone().map_err(TerminalSetupError::Tty)?;
+        UnixAnsiTerminal::from_tty_streams(reader, writer)
+    }
+}
+
+impl<I: Read + AsRawFd, O: Write + AsRawFd> UnixAnsiTerminal<I, O> {
+    /// Drives the terminal on the other end of `reader` and `writer`, like the master side of a
+    /// pseudoterminal. Their modes can be set with `set_mode` if they are terminals themselves.
+    pub fn from_tty_streams(reader: I, writer: O) -> Result<Self, TerminalSetupError> {
+        let stdin = StdInputHandle(Stream::from(reader.as_raw_fd()));
+        let stdout = StdOutputHandle(Stream::from(writer.as_raw_fd()));
+        let resize_signal = match (&stdin.0, &stdout.0) {
+            (Stream::Tty(_), _) | (_, Stream::Tty(_)) => {
+                Some(ResizeSignal::register().map_err(TerminalSetupError::SignalHandler)?)
+            }
+            _ => None,
+        };
+        Ok(UnixAnsiTerminal {
+            stdin,
+            stdout,
+            reader,
+            output: Output::new(writer, ScreenTracker::default()),
+            input: InputState::new(),
+            resize_signal,
+            job_control: None,
+        })
+    }
+}
+
+impl<I: Read, O: Write> UnixAnsiTerminal<I, O> {
+    /// Drives a terminal through arbitrary streams, like a socket for a remote session or
+    /// in-memory buffers. Since they aren't necessarily backed by file descriptors, `set_mode`
+    /// does nothing for them, and reading input can't time out.
+    pub fn from_streams(reader: I, writer: O) -> Self {
+        UnixAnsiTerminal {
+            stdin: StdInputHandle(Stream::Detached),
+            stdout: StdOutputHandle(Stream::Detached),
+            reader,
+            output: Output::new(writer, ScreenTracker::default()),
+            input: InputState::new(),
+            resize_signal: None,
+            job_control: None,
+        }
+    }
+
+    /// Makes sure that the terminal is restored even if the process panics or is killed by a
+    /// signal, for as long as the returned guard exists. See `RestoreGuard` for details.
+    pub fn restore_on_exit(&self) -> io::Result<RestoreGuard> {
+        RestoreGuard::install(self.restore()?)
+    }
+
+    /// Hands the terminal back to the shell whenever the process gets suspended with `SIGTSTP`:
+    /// its modes are reset to how they were before this changed them and the main screen buffer
+    /// is shown again. Once the process is continued, the modes and screen buffer in use before
+    /// are restored, and `Event::Redraw` is delivered so that the screen can be drawn again.
+    ///
+    /// In raw mode, Ctrl-Z doesn't send `SIGTSTP` but arrives as a key press; use `suspend` to
+    /// react to it.
+    pub fn enable_job_control(&mut self) -> io::Result<()> {
+        if self.job_control.is_none() {
+            self.job_control = Some(JobControl::register(self.restore()?)?);
+        }
+        Ok(())
+    }
+
+    /// Suspends the process like Ctrl-Z would in cooked mode, handing the terminal back to the
+    /// shell as described in `enable_job_control` (which this enables). Returns once the process
+    /// is continued.
+    pub fn suspend(&mut self) -> io::Result<()> {
+        self.enable_job_control()?;
+        self.flush()?;
+        signal_hook::low_level::raise(SIGTSTP)
+    }
+
+    /// Gathers what's needed to restore the terminal from a signal handler.
+    fn restore(&self) -> io::Result<Restore> {
+        let termios: Vec<_> = [&self.stdin.0, &self.stdout.0]
+            .iter()
+            .filter_map(|stream| match stream {
+                Stream::Tty(state) => Some((state.file_descriptor, state.termios_to_restore)),
+                Stream::NonTty(_) | Stream::Detached => None,
+            })
+            .collect();
+        let output = match &self.stdout.0 {
+            Stream::Tty(state) => Some(state.file_descriptor),
+            Stream::NonTty(_) | Stream::Detached => None,
+        };
+        Restore::new(&termios, output, self.output.observer.alternate())
+    }
+
+    /// Gets the window size from whichever of the streams the kernel knows it for.
+    fn window_size(&self) -> Option<(u16, u16)> {
+        window_size(self.stdout.0.raw_fd()).or_else(|| window_size(self.stdin.0.raw_fd()))
+    }
+
+    /// Runs `f` with stdin's settings changed by `modify`, restoring the settings it had before
+    /// afterwards, even if `f` fails. Streams that aren't terminals are left alone.
+    fn with_input_termios<T, M, F>(&mut self, modify: M, f: F) -> io::Result<T>
+    where
+        M: FnOnce(&TerminalState, &mut Termios),
+        F: FnOnce(&mut Self) -> io::Result<T>,
+    {
+        let to_restore = match &self.stdin.0 {
+            Stream::Tty(state) => {
+                let fd = state.file_descriptor;
+                let current = Termios::from_fd(fd)?;
+                let mut termios = current;
+                modify(state, &mut termios);
+                tcsetattr(fd, TCSANOW, &termios)?;
+                Some((fd, current))
+            }
+            Stream::NonTty(_) | Stream::Detached => None,
+        };
+        let result = f(self);
+        if let Some((fd, termios)) = to_restore {
+            tcsetattr(fd, TCSANOW, &termios)?;
+        }
+        result
+    }
+
+    /// Writes `prompt` and reads a line of input without echoing it, like a password. If `mask`
+    /// is given, it is echoed once for every character typed instead. The input's settings are
+    /// restored afterwards, even if reading fails. Input that was typed after the line is kept
+    /// for `read_event`. Ctrl-C fails with `io::ErrorKind::Interrupted` instead of raising
+    /// `SIGINT`, which would end the process before echo could be turned back on.
+    pub fn read_password(&mut self, prompt: &str, mask: Option<char>) -> io::Result<String> {
+        let hide_input = |_: &TerminalState, termios: &mut Termios| {
+            // Every key press is seen as it happens, so that the mask can be echoed and Ctrl-C
+            // doesn't wait for Enter.
+            termios.c_lflag &= !(ECHO | ECHONL | ICANON | ISIG);
+            termios.c_cc[VMIN] = 1;
+            termios.c_cc[VTIME] = 0;
+        };
+        self.with_input_termios(hide_input, |t| {
+            t.write(&format_args!("{}", prompt))?;
+            t.flush()?;
+            let password = t.read_hidden_line(mask);
+            t.write(&format_args!("\r\n"))?;
+            t.flush()?;
+            password
+        })
+    }
+
+    /// Reads keys through the decoder until Enter, so that input that was already buffered is
+    /// used and escape sequences count as the keys they stand for instead of as text. Keys that
+    /// don't type a character are ignored.
+    fn read_hidden_line(&mut self, mask: Option<char>) -> io::Result<String> {
+        let mut line = String::new();
+        loop {
+            self.flush()?;
+            let event = {
+                let (input, mut source) = self.split_input();
+                match input.poll_event(&mut source, None) {
+                    Ok(event) => event,
+                    // Ctrl-D on a line that isn't empty ends it early in canonical mode, and it
+                    // takes another one to close the stream.
+                    Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof && !line.is_empty() => {
+                        return Ok(line);
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+            let typed = match event {
+                Some(Event::Key(KeyEvent { code, modifiers })) => match code {
+                    KeyCode::Enter => return Ok(line),
+                    // Backspace, which only gets here if input isn't canonical
+                    KeyCode::Backspace => {
+                        if line.pop().is_some() && mask.is_some() {
+                            self.write(&format_args!("\x08 \x08"))?;
+                        }
+                        continue;
+                    }
+                    // Ctrl-C and Ctrl-D, which the terminal doesn't handle itself if signals are
+                    // off or input isn't canonical
+                    KeyCode::Char('c') if modifiers.control => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Interrupted,
+                            "reading the line was interrupted",
+                        ));
+                    }
+                    KeyCode::Char('d') if modifiers.control && line.is_empty() => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "terminal input stream closed",
+                        ));
+                    }
+                    KeyCode::Char(c) if !modifiers.control && !modifiers.alt => c.to_string(),
+                    _ => continue,
+                },
+                Some(Event::Paste(text)) => text,
+                _ => continue,
+            };
+            for c in typed.chars().filter(|c| !c.is_control()) {
+                line.push(c);
+                if let Some(mask) = mask {
+                    self.write(&format_args!("{}", mask))?;
+                }
+            }
+        }
+    }
+
+    /// Asks the terminal itself for its size, for when the kernel doesn't know it.
+    fn query_size(&mut self) -> io::Result<(u16, u16)> {
+        self.write(&AnsiEscape::QueryTextAreaSize)?;
+        self.flush()?;
+        let (input, mut source) = self.split_input();
+        let reply = input.wait_for_reply(&mut source, |reply| match reply {
+            Reply::TextAreaSize { rows, columns } => Some((columns, rows)),
+            _ => None,
+        })?;
+        if let Some(size) = reply {
+            return Ok(size);
+        }
+
+        // Not every terminal answers that, but all of them can say where the cursor ends up
+        // after trying to move it past the bottom right corner.
+        use self::CursorEscape::*;
+        for escape in &[
+            SavePosition,
+            Position(999, 999),
+            QueryPosition,
+            RestorePosition,
+        ] {
+            self.write(escape)?;
+        }
+        self.flush()?;
+        let (input, mut source) = self.split_input();
+        match input.wait_for_cursor_position(&mut source)? {
+            Some((row, column)) => Ok((column, row)),
+            None => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "terminal did not report its size",
+            )),
+        }
+    }
+}
+
+/// Gets the size of the terminal window that `fd` refers to, as `(columns, rows)`, if the kernel
+/// knows it.
+fn window_size(fd: Option<RawFd>) -> Option<(u16, u16)> {
+    let fd = fd?;
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    match unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } {
+        0 if size.ws_col != 0 && size.ws_row != 0 => Some((size.ws_col, size.ws_row)),
+        _ => None,
+    }
+}
+
+/// Forwards `SIGWINCH` to a socket that reading input also watches, so that resizes can be
+/// delivered as events.
+#[derive(Debug)]
+struct ResizeSignal {
+    id: SigId,
+    receiver: UnixStream,
+}
+
+impl ResizeSignal {
+    fn register() -> io::Result<Self> {
+        let (receiver, sender) = UnixStream::pair()?;
+        receiver.set_nonblocking(true)?;
+        let id = pipe::register(SIGWINCH, sender)?;
+        Ok(ResizeSignal { id, receiver })
+    }
+
+    /// Consumes all pending notifications, returning whether there were any.
+    fn take_pending(&self) -> bool {
+        take_pending(&self.receiver)
+    }
+}
+
+/// Consumes everything that signal handlers have written to `receiver` so far, returning whether
+/// there was anything.
+fn take_pending(mut receiver: &UnixStream) -> bool {
+    let mut buf = [0; 64];
+    let mut any = false;
+    while let Ok(n) = receiver.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        any = true;
+    }
+    any
+}
+
+impl Drop for ResizeSignal {
+    fn drop(&mut self) {
+        signal_hook::low_level::unregister(self.id);
+    }
+}
+
+/// Reads terminal input from `reader`, using `poll` on its file descriptor (if it has one) to
+/// wait for input with a timeout. Also watches for resizes and for being continued after a
+/// suspension.
+pub(crate) struct UnixEventSource<'a, I> {
+    reader: &'a mut I,
+    input: Option<RawFd>,
+    /// The descriptors to ask for the window size after a resize, in order of preference
+    window: [Option<RawFd>; 2],
+    resize_signal: Option<&'a ResizeSignal>,
+    job_control: Option<&'a JobControl>,
+}
+
+impl<'a, I: Read> UnixEventSource<'a, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<Received> {
+        match self.reader.read(buf) {
+            Ok(n) => Ok(Received::Bytes(n)),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => Ok(Received::Nothing),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<'a, I: Read> EventSource for UnixEventSource<'a, I> {
+    fn receive(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<Received> {
+        let input = match self.input {
+            Some(fd) => fd,
+            None => return self.read(buf),
+        };
+
+        let timeout_millis = timeout.map_or(-1, |t| {
+            // Round up, so that we never wake up just before the timeout is actually over.
+            let millis = t.as_nanos().div_ceil(1_000_000);
+            millis.min(c_int::MAX as u128) as c_int
+        });
+        let poll_fd = |fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // `poll` ignores negative descriptors.
+        let resize_fd = self.resize_signal.map_or(-1, |r| r.receiver.as_raw_fd());
+        let continue_fd = self.job_control.map_or(-1, |j| j.receiver.as_raw_fd());
+        let mut poll_fds = [poll_fd(input), poll_fd(resize_fd), poll_fd(continue_fd)];
+        let count = poll_fds.len() as libc::nfds_t;
+        match unsafe { libc::poll(poll_fds.as_mut_ptr(), count, timeout_millis) } {
+            -1 => match io::Error::last_os_error() {
+                ref e if e.kind() == io::ErrorKind::Interrupted => return Ok(Received::Nothing),
+                e => return Err(e),
+            },
+            0 => return Ok(Received::Nothing),
+            _ => (),
+        }
+
+        if poll_fds[1].revents != 0 && self.resize_signal.is_some_and(|r| r.take_pending()) {
+            let [first, second] = self.window;
+            if let Some((columns, rows)) = window_size(first).or_else(|| window_size(second)) {
+                return Ok(Received::Event(Event::Resize { columns, rows }));
+            }
+        }
+        if poll_fds[2].revents != 0 && self.job_control.is_some_and(|j| j.take_pending()) {
+            return Ok(Received::Event(Event::Redraw));
+        }
+        if poll_fds[0].revents == 0 {
+            return Ok(Received::Nothing);
+        }
+        // Since `poll` said so, this won't block. Buffered readers like `Stdin` pass reads this
+        // big straight through, so they never hold on to input that `poll` can't see.
+        self.read(buf)
+    }
+}
+
+impl<I: Read, O: Write> AnsiTerminal for UnixAnsiTerminal<I, O> {
+    fn set_mode(&mut self, options: TerminalModeOptions) -> Result<(), TerminalModeSetError> {
+        use self::{Stream::*, TerminalModeSetError::*};
+
+        if let Tty(stdin) = &mut self.stdin.0 {
+            stdin.set_mode(options.stdin, &options).map_err(Stdin)?;
+        }
+        if let Tty(stdout) = &mut self.stdout.0 {
+            stdout.set_mode(options.stdout, &options).map_err(Stdout)?;
+        }
+        Ok(())
+    }
+
+    fn write<T: TerminalOutput>(&mut self, t: &T) -> io::Result<()> {
+        self.output.write(t)
+    }
+
+    fn set_color_depth(&mut self, depth: ColorDepth) {
+        self.output.color_depth = depth;
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    fn frame<T, F>(&mut self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Self) -> io::Result<T>,
+    {
+        output::frame(self, |t| &mut t.output, f)
+    }
+
+    fn read_event(&mut self) -> io::Result<Event> {
+        let (input, mut source) = self.split_input();
+        loop {
+            if let Some(event) = input.poll_event(&mut source, None)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        let (input, mut source) = self.split_input();
+        input.poll_event(&mut source, Some(timeout))
+    }
+
+    fn set_escape_timeout(&mut self, timeout: Duration) {
+        self.input.set_escape_timeout(timeout);
+    }
+
+    fn set_reply_timeout(&mut self, timeout: Duration) {
+        self.input.set_reply_timeout(timeout);
+    }
+
+    fn size(&mut self) -> io::Result<(u16, u16)> {
+        match (self.window_size(), &self.stdin.0) {
+            (Some(size), _) => Ok(size),
+            (None, Stream::Tty(_)) | (None, Stream::Detached) => {
+                self.with_raw_input(Self::query_size)
+            }
+            (None, Stream::NonTty(_)) => Err(io::Error::other(
+                "unable to get the size of a terminal that is not connected",
+            )),
+        }
+    }
+
+    fn query_cursor_position(&mut self) -> io::Result<(u16, u16)> {
+        input::query_cursor_position(self)
+    }
+
+    fn query_clipboard(&mut self, target: ClipboardTarget) -> io::Result<String> {
+        input::query_clipboard(self, target)
+    }
+
+    fn query_primary_device_attributes(&mut self) -> io::Result<PrimaryDeviceAttributes> {
+        input::query_primary_device_attributes(self)
+    }
+
+    fn query_secondary_device_attributes(
+        &mut self,
+    ) -> io::Result<Option<SecondaryDeviceAttributes>> {
+        input::query_secondary_device_attributes(self)
+    }
+
+    fn query_terminal_version(&mut self) -> io::Result<Option<String>> {
+        input::query_terminal_version(self)
+    }
+
+    fn query_private_mode(&mut self, mode: u16) -> io::Result<ModeState> {
+        input::query_private_mode(self, mode)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::detect(match self.stdout.0 {
+            Stream::Tty(_) => true,
+            Stream::NonTty(_) | Stream::Detached => false,
+        })
+    }
+}
+
+impl<I: Read, O: Write> RawInput for UnixAnsiTerminal<I, O> {
+    type Source<'a>
+        = UnixEventSource<'a, I>
+    where
+        Self: 'a;
+
+    fn split_input(&mut self) -> (&mut InputState, Self::Source<'_>) {
+        let source = UnixEventSource {
+            reader: &mut self.reader,
+            input: self.stdin.0.raw_fd(),
+            window: [self.stdout.0.raw_fd(), self.stdin.0.raw_fd()],
+            resize_signal: self.resize_signal.as_ref(),
+            job_control: self.job_control.as_ref(),
+        };
+        (&mut self.input, source)
+    }
+
+    /// Runs `f` with stdin in raw mode, so that the terminal's replies to queries can be read as
+    /// soon as they arrive instead of being echoed back to it.
+    fn with_raw_input<T, F>(&mut self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Self) -> io::Result<T>,
+    {
+        self.with_input_termios(|state, termios| *termios = state.raw_termios, f)
+    }
+}
+
+impl<I: Read, O: Write> Drop for UnixAnsiTerminal<I, O> {
+    fn drop(&mut self) {
+        let _ = SetGraphicsRenditionEscape::Reset.fmt(&mut self.output.writer);
+        if let (true, Stream::Tty(_)) = (self.output.cursor_style.changed(), &self.stdout.0) {
+            let _ = CursorEscape::DefaultCursorStyle.fmt(&mut self.output.writer);
+        }
+        let _ = self.output.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::input::keyboard::KeyModifiers};
+
+    fn read_password(input: &[u8], mask: Option<char>) -> (io::Result<String>, Vec<u8>) {
+        let mut output = Vec::new();
+        let password = {
+            let mut t = UnixAnsiTerminal::from_streams(input, &mut output);
+            t.read_password("Password: ", mask)
+        };
+        (password, output)
+    }
+
+    #[test]
+    fn password_is_not_echoed() {
+        let (password, output) = read_password(b"secret\r", None);
+        assert_eq!(password.unwrap(), "secret");
+        assert_eq!(output, b"Password: \r\n\x1B[0m");
+    }
+
+    #[test]
+    fn mask_is_echoed_and_erased() {
+        let (password, output) = read_password(b"ab\x7Fc\x7F\x7F\x7Fd\r", Some('*'));
+        assert_eq!(password.unwrap(), "d");
+        assert_eq!(
+            output,
+            &b"Password: **\x08 \x08*\x08 \x08\x08 \x08*\r\n\x1B[0m"[..]
+        );
+    }
+
+    #[test]
+    fn keys_that_do_not_type_are_ignored() {
+        let (password, _) = read_password(b"a\x1B[A\x1B[1;5Cb\x1B[200~c\td\x1B[201~\r", None);
+        assert_eq!(password.unwrap(), "abcd");
+    }
+
+    #[test]
+    fn ctrl_c_interrupts() {
+        let (password, output) = read_password(b"ab\x03cd\r", Some('*'));
+        assert_eq!(password.unwrap_err().kind(), io::ErrorKind::Interrupted);
+        assert_eq!(output, b"Password: **\r\n\x1B[0m");
+    }
+
+    #[test]
+    fn ctrl_d_closes_only_an_empty_line() {
+        let (password, _) = read_password(b"\x04", None);
+        assert_eq!(password.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        let (password, _) = read_password(b"ab\x04c\r", None);
+        assert_eq!(password.unwrap(), "abc");
+        let (password, _) = read_password(b"ab", None);
+        assert_eq!(password.unwrap(), "ab");
+    }
+
+    #[test]
+    fn input_after_the_line_is_kept() {
+        let mut t = UnixAnsiTerminal::from_streams(&b"secret\rx\x1B[B"[..], io::sink());
+        assert_eq!(t.read_password("", None).unwrap(), "secret");
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::default()));
+        assert_eq!(t.read_event().unwrap(), key(KeyCode::Char('x')));
+        assert_eq!(t.read_event().unwrap(), key(KeyCode::Down));
+    }
+}
@@ -0,0 +1,254 @@
+use {
+    crate::{
+        escapes::{
+            cursor::CursorEscape, formatting::SetGraphicsRenditionEscape, AnsiEscape, Observe,
+        },
+        TerminalOutput,
+    },
+    signal_hook::{
+        consts::{SIGINT, SIGQUIT, SIGTERM},
+        low_level::{emulate_default_handler, register, unregister},
+        SigId,
+    },
+    std::{
+        io,
+        os::unix::io::RawFd,
+        panic,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Weak,
+        },
+    },
+    termios::{tcsetattr, Termios, TCSANOW},
+};
+
+const ESC: u8 = 0x1B;
+/// What switching to the alternate screen buffer and back has in common, right up to the final
+/// `h` or `l`.
+const SWITCH_SCREEN_BUFFER: &[u8] = b"\x1B[?1049";
+
+/// Watches the bytes written to a terminal for switches between the main and alternate screen
+/// buffers, so that whatever is active is known without asking the terminal.
+#[derive(Debug, Default)]
+pub(crate) struct ScreenTracker {
+    alternate: Arc<AtomicBool>,
+    /// How much of `SWITCH_SCREEN_BUFFER` the last bytes written matched
+    matched: usize,
+}
+
+impl Observe for ScreenTracker {
+    fn observe(&mut self, bytes: &[u8]) {
+        let full = SWITCH_SCREEN_BUFFER.len();
+        for &b in bytes {
+            self.matched = match (self.matched, b) {
+                (n, b'h') if n == full => {
+                    self.alternate.store(true, Ordering::SeqCst);
+                    0
+                }
+                (n, b'l') if n == full => {
+                    self.alternate.store(false, Ordering::SeqCst);
+                    0
+                }
+                (n, b) if n < full && b == SWITCH_SCREEN_BUFFER[n] => n + 1,
+                (_, ESC) => 1,
+                _ => 0,
+            };
+        }
+    }
+}
+
+impl ScreenTracker {
+    /// Whether the alternate screen buffer is active, as it changes
+    pub(crate) fn alternate(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.alternate)
+    }
+}
+
+/// Everything needed to put a terminal back the way it was, gathered ahead of time so that it can
+/// be done from a signal handler.
+#[derive(Debug)]
+pub(crate) struct Restore {
+    armed: AtomicBool,
+    /// Duplicates of the terminal's descriptors, with the settings they had before the
+    /// `AnsiTerminal` changed them
+    pub(crate) termios: Vec<(RawFd, Termios)>,
+    pub(crate) output: Option<RawFd>,
+    pub(crate) alternate_screen: Arc<AtomicBool>,
+    reset: Vec<u8>,
+    leave_alternate_screen: Vec<u8>,
+}
+
+impl Restore {
+    /// `termios` are the descriptors to reset the modes of and what to reset them to, and `output`
+    /// is where the escapes that reset the terminal are written, if anywhere.
+    pub(crate) fn new(
+        termios: &[(RawFd, Termios)],
+        output: Option<RawFd>,
+        alternate_screen: Arc<AtomicBool>,
+    ) -> io::Result<Self> {
+        let mut reset = Vec::new();
+        CursorEscape::Show.fmt(&mut reset)?;
+        SetGraphicsRenditionEscape::Reset.fmt(&mut reset)?;
+        let mut leave_alternate_screen = Vec::new();
+        AnsiEscape::SwitchToMainScreenBuffer.fmt(&mut leave_alternate_screen)?;
+
+        // Dropping this closes the descriptors that have been duplicated so far.
+        let mut restore = Restore {
+            armed: AtomicBool::new(true),
+            termios: Vec::with_capacity(termios.len()),
+            output: None,
+            alternate_screen,
+            reset,
+            leave_alternate_screen,
+        };
+        for (fd, termios) in termios {
+            restore.termios.push((duplicate(*fd)?, *termios));
+        }
+        if let Some(fd) = output {
+            restore.output = Some(duplicate(fd)?);
+        }
+        Ok(restore)
+    }
+
+    /// Only does what is safe to do in a signal handler.
+    pub(crate) fn run(&self) {
+        if !self.armed.load(Ordering::SeqCst) {
+            return;
+        }
+        for (fd, termios) in &self.termios {
+            let _ = tcsetattr(*fd, TCSANOW, termios);
+        }
+        if let Some(fd) = self.output {
+            if self.alternate_screen.load(Ordering::SeqCst) {
+                write_all(fd, &self.leave_alternate_screen);
+            }
+            write_all(fd, &self.reset);
+        }
+    }
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let descriptors = self.termios.iter().map(|(fd, _)| *fd).chain(self.output);
+        for fd in descriptors {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+/// Writes `bytes` to `fd` with nothing but system calls.
+pub(crate) fn write_all(fd: RawFd, mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        match unsafe { libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len()) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            n if n <= 0 => return,
+            n => bytes = &bytes[n as usize..],
+        }
+    }
+}
+
+fn duplicate(fd: RawFd) -> io::Result<RawFd> {
+    match unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) } {
+        -1 => Err(io::Error::last_os_error()),
+        duplicate => Ok(duplicate),
+    }
+}
+
+/// Restores a terminal if the process panics or is killed by `SIGINT`, `SIGTERM` or `SIGQUIT`
+/// while it exists: the terminal's modes are reset to how they were before the `AnsiTerminal`
+/// changed them, the alternate screen buffer is left if it was entered, the cursor is shown and
+/// graphics renditions are reset. Created with `UnixAnsiTerminal::restore_on_exit`.
+///
+/// Since the signals still end the process afterwards, installing this replaces any other way of
+/// handling them the application might have. Panics are handed on to the previous panic hook
+/// after restoring the terminal, so that their messages are readable.
+#[derive(Debug)]
+pub struct RestoreGuard {
+    restore: Arc<Restore>,
+    signal_ids: Vec<SigId>,
+}
+
+impl RestoreGuard {
+    pub(crate) fn install(restore: Restore) -> io::Result<Self> {
+        let mut guard = RestoreGuard {
+            restore: Arc::new(restore),
+            signal_ids: Vec::new(),
+        };
+        for &signal in &[SIGINT, SIGTERM, SIGQUIT] {
+            let restore = Arc::clone(&guard.restore);
+            let id = unsafe {
+                register(signal, move || {
+                    restore.run();
+                    let _ = emulate_default_handler(signal);
+                })
+            }?;
+            guard.signal_ids.push(id);
+        }
+
+        let restore = Arc::downgrade(&guard.restore);
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(restore) = Weak::upgrade(&restore) {
+                restore.run();
+            }
+            previous_hook(info);
+        }));
+        Ok(guard)
+    }
+}
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        // The panic hook can't be taken back without also taking any that were set after it, so
+        // it stays in place but does nothing anymore.
+        self.restore.armed.store(false, Ordering::SeqCst);
+        for id in self.signal_ids.drain(..) {
+            unregister(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Write};
+
+    fn alternate_after(writes: &[&[u8]]) -> bool {
+        let mut tracker = ScreenTracker::default();
+        let mut out = Vec::new();
+        for bytes in writes {
+            tracker.track(&mut out).write_all(bytes).unwrap();
+        }
+        tracker.alternate().load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn tracks_screen_switches() {
+        assert!(alternate_after(&[b"text\x1B[?1049hmore"]));
+        assert!(!alternate_after(&[b"\x1B[?1049h\x1B[2J\x1B[?1049l"]));
+        assert!(alternate_after(&[b"\x1B[?1049l\x1B[?1049h"]));
+    }
+
+    #[test]
+    fn switches_can_be_split_across_writes() {
+        assert!(alternate_after(&[b"\x1B", b"[?10", b"49", b"h"]));
+        let bytes = b"\x1B[?1049h\x1B[?1049l";
+        let split: Vec<&[u8]> = bytes.chunks(1).collect();
+        assert!(!alternate_after(&split));
+        assert!(alternate_after(&split[..8]));
+    }
+
+    #[test]
+    fn near_misses_are_not_switches() {
+        assert!(!alternate_after(&[b"\x1B[?104xh"]));
+        assert!(!alternate_after(&[b"\x1B[?104", b"9x", b"h"]));
+        assert!(!alternate_after(&[b"\x1B[?1047h\x1B[?10049h"]));
+        assert!(!alternate_after(&[b"[?1049h"]));
+    }
+
+    #[test]
+    fn escape_restarts_the_match() {
+        assert!(alternate_after(&[b"\x1B[?10\x1B[?1049h"]));
+        assert!(alternate_after(&[b"\x1B[?1049", b"\x1B[?1049h"]));
+        assert!(alternate_after(&[b"\x1B\x1B[?1049h"]));
+    }
+}
@@ -1,94 +1,361 @@
 use {
-    escapes::formatting::SetGraphicsRenditionEscape,
-    std::io::{self, stdout},
+    capabilities::{
+        Capabilities, ColorDepth, ModeState, PrimaryDeviceAttributes, SecondaryDeviceAttributes,
+    },
+    escapes::{cursor::CursorEscape, formatting::SetGraphicsRenditionEscape, osc::ClipboardTarget},
+    input::{self, Event, EventSource, InputState, RawInput, Received},
+    output::{self, Output},
+    std::{
+        io::{self, stdin, stdout, Read, Write},
+        mem, ptr,
+        time::{Duration, Instant},
+    },
     winapi::{
-        shared::{minwindef::DWORD, ntdef::HANDLE},
+        shared::{
+            minwindef::{DWORD, LPVOID},
+            ntdef::HANDLE,
+            winerror::WAIT_TIMEOUT,
+        },
         um::{
-            consoleapi::{GetConsoleMode, SetConsoleMode},
+            consoleapi::{GetConsoleMode, PeekConsoleInputW, ReadConsoleInputW, SetConsoleMode},
+            fileapi::ReadFile,
             processenv::GetStdHandle,
-            winbase::{STD_INPUT_HANDLE, STD_OUTPUT_HANDLE},
+            synchapi::WaitForSingleObject,
+            winbase::{INFINITE, STD_INPUT_HANDLE, STD_OUTPUT_HANDLE, WAIT_OBJECT_0},
             wincon::{
-                DISABLE_NEWLINE_AUTO_RETURN, ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT,
-                ENABLE_PROCESSED_INPUT, ENABLE_PROCESSED_OUTPUT,
-                ENABLE_VIRTUAL_TERMINAL_PROCESSING, ENABLE_WRAP_AT_EOL_OUTPUT,
+                GetConsoleScreenBufferInfo, DISABLE_NEWLINE_AUTO_RETURN, ENABLE_ECHO_INPUT,
+                ENABLE_LINE_INPUT, ENABLE_PROCESSED_INPUT, ENABLE_PROCESSED_OUTPUT,
+                ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+                ENABLE_WRAP_AT_EOL_OUTPUT, INPUT_RECORD, KEY_EVENT,
             },
         },
     },
     AnsiTerminal, TerminalModeOptions, TerminalModeSetError, TerminalOutput, TerminalSetupError,
 };
 
+/// An `AnsiTerminal` that reads input from `I` and writes output to `O`, which are the process's
+/// standard streams by default.
 #[derive(Debug)]
-pub struct WindowsAnsiTerminal {
+pub struct WindowsAnsiTerminal<I: Read = io::Stdin, O: Write = io::Stdout> {
     stdin: StdInputHandle,
     stdout: StdOutputHandle,
+    reader: I,
+    output: Output<O, ()>,
+    input: InputState,
 }
 
 impl WindowsAnsiTerminal {
     pub fn new() -> Result<Self, TerminalSetupError> {
         use TerminalSetupError::*;
 
-        let stdin = StdInputHandle::new().unwrap().map_err(Stdin)?;
-        let stdout = StdOutputHandle::new().unwrap().map_err(Stdout)?;
+        let stdin_handle = StdInputHandle::new().unwrap().map_err(Stdin)?;
+        let stdout_handle = StdOutputHandle::new().unwrap().map_err(Stdout)?;
 
-        let mut t = WindowsAnsiTerminal { stdin, stdout };
+        let mut t = WindowsAnsiTerminal {
+            stdin: stdin_handle,
+            stdout: stdout_handle,
+            reader: stdin(),
+            output: Output::new(stdout(), ()),
+            input: InputState::new(),
+        };
         if let StreamHandle::Console(out) = &mut t.stdout.0 {
-            out.set_flags(ENABLE_VIRTUAL_TERMINAL_PROCESSING)
+            out.set_mode(out.state | ENABLE_VIRTUAL_TERMINAL_PROCESSING)
                 .map_err(TerminalModeSetError::Stdout)?;
         }
         Ok(t)
     }
 }
 
-impl Drop for WindowsAnsiTerminal {
+impl<I: Read, O: Write> WindowsAnsiTerminal<I, O> {
+    /// Drives a terminal through arbitrary streams, like a socket for a remote session or
+    /// in-memory buffers. Since they aren't consoles, `set_mode` does nothing for them, and
+    /// reading input can't time out.
+    pub fn from_streams(reader: I, writer: O) -> Self {
+        WindowsAnsiTerminal {
+            stdin: StdInputHandle(StreamHandle::Detached),
+            stdout: StdOutputHandle(StreamHandle::Detached),
+            reader,
+            output: Output::new(writer, ()),
+            input: InputState::new(),
+        }
+    }
+}
+
+impl<I: Read, O: Write> RawInput for WindowsAnsiTerminal<I, O> {
+    type Source<'a>
+        = WindowsEventSource<'a, I>
+    where
+        Self: 'a;
+
+    fn split_input(&mut self) -> (&mut InputState, Self::Source<'_>) {
+        let source = WindowsEventSource {
+            reader: &mut self.reader,
+            console: match &self.stdin.0 {
+                StreamHandle::Console(c) => Some(c.handle),
+                StreamHandle::NonConsole { .. } | StreamHandle::Detached => None,
+            },
+        };
+        (&mut self.input, source)
+    }
+
+    /// Runs `f` with the console's input in VT mode and without line input or echo, so that the
+    /// terminal's replies to queries can be read as soon as they arrive instead of being echoed
+    /// back to it. The previous mode is restored afterwards, even if `f` fails. Handles that
+    /// aren't consoles are left alone.
+    fn with_raw_input<T, F>(&mut self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Self) -> io::Result<T>,
+    {
+        let to_restore = match &self.stdin.0 {
+            StreamHandle::Console(c) => {
+                let raw = (c.state | ENABLE_VIRTUAL_TERMINAL_INPUT)
+                    & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT);
+                c.apply_mode(raw)?;
+                Some(c.state)
+            }
+            StreamHandle::NonConsole { .. } | StreamHandle::Detached => None,
+        };
+        let result = f(self);
+        if let (Some(state), StreamHandle::Console(c)) = (to_restore, &self.stdin.0) {
+            c.apply_mode(state)?;
+        }
+        result
+    }
+}
+
+impl<I: Read, O: Write> Drop for WindowsAnsiTerminal<I, O> {
     fn drop(&mut self) {
-        let _ = SetGraphicsRenditionEscape::Reset.fmt(&mut stdout());
+        let _ = SetGraphicsRenditionEscape::Reset.fmt(&mut self.output.writer);
+        if let (true, StreamHandle::Console(_)) =
+            (self.output.cursor_style.changed(), &self.stdout.0)
+        {
+            let _ = CursorEscape::DefaultCursorStyle.fmt(&mut self.output.writer);
+        }
+        let _ = self.output.writer.flush();
     }
 }
 
-impl AnsiTerminal for WindowsAnsiTerminal {
+impl<I: Read, O: Write> AnsiTerminal for WindowsAnsiTerminal<I, O> {
     fn set_mode(&mut self, options: TerminalModeOptions) -> Result<(), TerminalModeSetError> {
         use TerminalModeSetError::*;
 
-        let mut stdin_flags = 0;
-        let mut stdout_flags = 0;
-
         let WindowsTerminalMode {
             disable_newline_auto_return,
             echo_input,
             line_input,
             processed_input,
             processed_output,
+            virtual_terminal_input,
             wrap_at_eol_output,
         } = WindowsTerminalMode::from(options);
 
+        // Every flag the options cover is either set or cleared, so that switching from raw back
+        // to cooked undoes what raw did; anything else, like VT processing, is kept as it is.
         macro_rules! map_option {
-            ($flags_set:ident, $option:expr, $flag:expr) => {
+            ($mode:ident, $option:expr, $flag:expr) => {
                 if $option {
-                    $flags_set |= $flag;
+                    $mode |= $flag;
+                } else {
+                    $mode &= !$flag;
                 }
             };
         }
-        map_option!(stdin_flags, echo_input, ENABLE_ECHO_INPUT);
-        map_option!(stdin_flags, line_input, ENABLE_LINE_INPUT);
-        map_option!(stdin_flags, processed_input, ENABLE_PROCESSED_INPUT);
-        map_option!(
-            stdout_flags,
-            disable_newline_auto_return,
-            DISABLE_NEWLINE_AUTO_RETURN
-        );
-        map_option!(stdout_flags, processed_output, ENABLE_PROCESSED_OUTPUT);
-        map_option!(stdout_flags, wrap_at_eol_output, ENABLE_WRAP_AT_EOL_OUTPUT);
 
         use StreamHandle::*;
 
         if let Console(c) = &mut self.stdin.0 {
-            c.set_flags(stdin_flags).map_err(Stdin)?;
+            let mut mode = c.state;
+            map_option!(mode, echo_input, ENABLE_ECHO_INPUT);
+            map_option!(mode, line_input, ENABLE_LINE_INPUT);
+            map_option!(mode, processed_input, ENABLE_PROCESSED_INPUT);
+            map_option!(mode, virtual_terminal_input, ENABLE_VIRTUAL_TERMINAL_INPUT);
+            c.set_mode(mode).map_err(Stdin)?;
         }
         if let Console(c) = &mut self.stdout.0 {
-            c.set_flags(stdout_flags).map_err(Stdout)?;
+            let mut mode = c.state;
+            map_option!(
+                mode,
+                disable_newline_auto_return,
+                DISABLE_NEWLINE_AUTO_RETURN
+            );
+            map_option!(mode, processed_output, ENABLE_PROCESSED_OUTPUT);
+            map_option!(mode, wrap_at_eol_output, ENABLE_WRAP_AT_EOL_OUTPUT);
+            c.set_mode(mode).map_err(Stdout)?;
         }
         Ok(())
     }
+
+    fn write<T: TerminalOutput>(&mut self, t: &T) -> io::Result<()> {
+        self.output.write(t)
+    }
+
+    fn set_color_depth(&mut self, depth: ColorDepth) {
+        self.output.color_depth = depth;
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    fn frame<T, F>(&mut self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Self) -> io::Result<T>,
+    {
+        output::frame(self, |t| &mut t.output, f)
+    }
+
+    fn read_event(&mut self) -> io::Result<Event> {
+        let (input, mut source) = self.split_input();
+        loop {
+            if let Some(event) = input.poll_event(&mut source, None)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        let (input, mut source) = self.split_input();
+        input.poll_event(&mut source, Some(timeout))
+    }
+
+    fn set_escape_timeout(&mut self, timeout: Duration) {
+        self.input.set_escape_timeout(timeout);
+    }
+
+    fn set_reply_timeout(&mut self, timeout: Duration) {
+        self.input.set_reply_timeout(timeout);
+    }
+
+    fn size(&mut self) -> io::Result<(u16, u16)> {
+        let handle = self.stdout.0.handle().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "unable to get the size of a terminal that is not a console",
+            )
+        })?;
+        let mut info = unsafe { mem::zeroed() };
+        match unsafe { GetConsoleScreenBufferInfo(handle, &mut info) } {
+            0 => Err(io::Error::last_os_error()),
+            _ => {
+                let window = info.srWindow;
+                Ok((
+                    (window.Right - window.Left + 1) as u16,
+                    (window.Bottom - window.Top + 1) as u16,
+                ))
+            }
+        }
+    }
+
+    fn query_cursor_position(&mut self) -> io::Result<(u16, u16)> {
+        input::query_cursor_position(self)
+    }
+
+    fn query_clipboard(&mut self, target: ClipboardTarget) -> io::Result<String> {
+        input::query_clipboard(self, target)
+    }
+
+    fn query_primary_device_attributes(&mut self) -> io::Result<PrimaryDeviceAttributes> {
+        input::query_primary_device_attributes(self)
+    }
+
+    fn query_secondary_device_attributes(
+        &mut self,
+    ) -> io::Result<Option<SecondaryDeviceAttributes>> {
+        input::query_secondary_device_attributes(self)
+    }
+
+    fn query_terminal_version(&mut self) -> io::Result<Option<String>> {
+        input::query_terminal_version(self)
+    }
+
+    fn query_private_mode(&mut self, mode: u16) -> io::Result<ModeState> {
+        input::query_private_mode(self, mode)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::detect(match self.stdout.0 {
+            StreamHandle::Console(_) => true,
+            StreamHandle::NonConsole { .. } | StreamHandle::Detached => false,
+        })
+    }
+}
+
+/// Reads the VT sequences that the console emits for input when
+/// `ENABLE_VIRTUAL_TERMINAL_INPUT` is set. Streams that aren't consoles, like pipes, are read from
+/// `reader` instead, which can't time out.
+pub(crate) struct WindowsEventSource<'a, I> {
+    reader: &'a mut I,
+    console: Option<HANDLE>,
+}
+
+impl<'a, I: Read> EventSource for WindowsEventSource<'a, I> {
+    fn receive(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<Received> {
+        let handle = match self.console {
+            Some(handle) => handle,
+            None => {
+                return match self.reader.read(buf) {
+                    Ok(n) => Ok(Received::Bytes(n)),
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => Ok(Received::Nothing),
+                    Err(e) => Err(e),
+                };
+            }
+        };
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            let timeout_millis = deadline.map_or(INFINITE, |d| {
+                let remaining = d.saturating_duration_since(Instant::now());
+                remaining.as_millis().min(u128::from(INFINITE - 1)) as DWORD
+            });
+            match unsafe { WaitForSingleObject(handle, timeout_millis) } {
+                WAIT_OBJECT_0 => (),
+                WAIT_TIMEOUT => return Ok(Received::Nothing),
+                _ => return Err(io::Error::last_os_error()),
+            }
+            // The handle is also signaled for key releases, focus changes and the like, which
+            // `ReadFile` skips over while it blocks for characters.
+            if discard_non_character_records(handle)? {
+                break;
+            }
+        }
+
+        let mut read = 0;
+        match unsafe {
+            ReadFile(
+                handle,
+                buf.as_mut_ptr() as LPVOID,
+                buf.len() as DWORD,
+                &mut read,
+                ptr::null_mut(),
+            )
+        } {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(Received::Bytes(read as usize)),
+        }
+    }
+}
+
+/// Removes the records at the front of the console's input buffer that don't carry characters,
+/// returning whether a character is waiting afterwards.
+fn discard_non_character_records(handle: HANDLE) -> io::Result<bool> {
+    loop {
+        let mut record: INPUT_RECORD = unsafe { mem::zeroed() };
+        let mut count = 0;
+        if unsafe { PeekConsoleInputW(handle, &mut record, 1, &mut count) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if count == 0 {
+            return Ok(false);
+        }
+        if record.EventType == KEY_EVENT {
+            let key = unsafe { record.Event.KeyEvent() };
+            if key.bKeyDown != 0 && unsafe { *key.uChar.UnicodeChar() } != 0 {
+                return Ok(true);
+            }
+        }
+        if unsafe { ReadConsoleInputW(handle, &mut record, 1, &mut count) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -99,10 +366,18 @@ pub struct ConsoleHandle {
 }
 
 impl ConsoleHandle {
-    fn set_flags(&mut self, flags: DWORD) -> io::Result<()> {
-        // FIXME: Only commit this AFTER the change has been accepted.
-        self.state |= flags;
-        match unsafe { SetConsoleMode(self.handle, self.state) } {
+    /// Switches the console to `mode` and remembers it as the mode later changes start from,
+    /// once the console has accepted it.
+    fn set_mode(&mut self, mode: DWORD) -> io::Result<()> {
+        self.apply_mode(mode)?;
+        self.state = mode;
+        Ok(())
+    }
+
+    /// Switches the console to `mode` without remembering it, for modes that only last as long
+    /// as an operation.
+    fn apply_mode(&self, mode: DWORD) -> io::Result<()> {
+        match unsafe { SetConsoleMode(self.handle, mode) } {
             0 => Err(io::Error::last_os_error()),
             _ => Ok(()),
         }
@@ -124,9 +399,19 @@ impl Drop for ConsoleHandle {
 pub enum StreamHandle {
     NonConsole { handle: HANDLE },
     Console(ConsoleHandle),
+    /// A stream with no handle behind it, like an in-memory buffer
+    Detached,
 }
 
 impl StreamHandle {
+    fn handle(&self) -> Option<HANDLE> {
+        match self {
+            StreamHandle::NonConsole { handle } => Some(*handle),
+            StreamHandle::Console(c) => Some(c.handle),
+            StreamHandle::Detached => None,
+        }
+    }
+
     unsafe fn from_std_stream(std_handle: DWORD) -> Result<StreamHandle, io::Error> {
         let handle = GetStdHandle(std_handle);
 
@@ -179,6 +464,7 @@ pub struct WindowsTerminalMode {
     line_input: bool,
     processed_input: bool,
     processed_output: bool,
+    virtual_terminal_input: bool,
     wrap_at_eol_output: bool,
 }
 
@@ -186,7 +472,18 @@ impl From<TerminalModeOptions> for WindowsTerminalMode {
     fn from(x: TerminalModeOptions) -> Self {
         use TerminalChannelMode::*;
 
-        let TerminalModeOptions { stdin, stdout } = x;
+        let TerminalModeOptions {
+            stdin,
+            stdout,
+            echo,
+            canonical,
+            signals,
+            output_processing,
+            // Consoles don't have anything like these.
+            flow_control: _,
+            read_minimum: _,
+            read_timeout: _,
+        } = x;
 
         let stdin_cooked_flag = match stdin {
             Raw => false,
@@ -200,10 +497,11 @@ impl From<TerminalModeOptions> for WindowsTerminalMode {
 
         WindowsTerminalMode {
             disable_newline_auto_return: !stdin_cooked_flag,
-            echo_input: stdin_cooked_flag,
-            line_input: stdin_cooked_flag,
-            processed_input: stdin_cooked_flag,
-            processed_output: stdout_cooked_flag,
+            echo_input: echo.unwrap_or(stdin_cooked_flag),
+            line_input: canonical.unwrap_or(stdin_cooked_flag),
+            processed_input: signals.unwrap_or(stdin_cooked_flag),
+            processed_output: output_processing.unwrap_or(stdout_cooked_flag),
+            virtual_terminal_input: !stdin_cooked_flag,
             wrap_at_eol_output: stdout_cooked_flag,
         }
     }